@@ -1,18 +1,21 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult};
+use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult};
 use cw2::set_contract_version;
 
+use crate::contract_admin::route_admin;
 use crate::contract_callback::route_callback;
 use crate::contract_execute::route_execute;
+use crate::contract_migrate::route_migrate;
 use crate::contract_query::route_query;
+use crate::contract_reply::route_reply;
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
 use crate::state::CONFIG;
 
 // version info for migration info
-const CONTRACT_NAME: &str = "deposit-handler";
-const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const CONTRACT_NAME: &str = "deposit-handler";
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -22,7 +25,12 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
-    CONFIG.save(deps.storage, &msg.config)?;
+
+    // make sure the admin and routers are valid addresses before storing them
+    let config = msg.config;
+    config.validate(deps.api)?;
+
+    CONFIG.save(deps.storage, &config)?;
 
     Ok(Response::new().add_attribute("method", "instantiate"))
 }
@@ -36,6 +44,12 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     return match msg {
         ExecuteMsg::Callback(callback) => route_callback(deps, env, info, callback),
+        ExecuteMsg::UpdateConfig { .. }
+        | ExecuteMsg::ProposeNewAdmin { .. }
+        | ExecuteMsg::AcceptAdmin {}
+        | ExecuteMsg::CancelAdminTransfer {}
+        | ExecuteMsg::SetGuardian { .. }
+        | ExecuteMsg::SetPauseState { .. } => route_admin(deps, env, info, msg),
         _ => route_execute(deps, env, info, msg),
     };
 }
@@ -44,3 +58,13 @@ pub fn execute(
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     return route_query(deps, env, msg);
 }
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    return route_reply(deps, env, msg);
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    return route_migrate(deps, env, msg);
+}
@@ -0,0 +1,313 @@
+use cosmwasm_std::{Decimal, DepsMut, Env, Event, MessageInfo, Response, StdResult, Storage};
+use cw_utils::Expiration;
+
+use crate::{
+    msg::{AssetConfigUpdate, ExecuteMsg},
+    state::{CONFIG, PAUSE_STATE, PENDING_ADMIN, TOTALS},
+    typing::{IdValidation, PendingAdmin, RatioSource},
+    ContractError,
+};
+
+pub fn route_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::UpdateConfig {
+            assets,
+            ratio_source,
+            ratio_tolerance,
+            refund_excess,
+            swap_contract,
+            id_validation,
+            bond_timeout,
+        } => update_config(
+            deps,
+            env,
+            info,
+            assets,
+            ratio_source,
+            ratio_tolerance,
+            refund_excess,
+            swap_contract,
+            id_validation,
+            bond_timeout,
+        ),
+        ExecuteMsg::ProposeNewAdmin { new_admin, expires } => {
+            propose_new_admin(deps, env, info, new_admin, expires)
+        }
+        ExecuteMsg::AcceptAdmin {} => accept_admin(deps, env, info),
+        ExecuteMsg::CancelAdminTransfer {} => cancel_admin_transfer(deps, info),
+        ExecuteMsg::SetGuardian { guardian } => set_guardian(deps, info, guardian),
+        ExecuteMsg::SetPauseState {
+            bonding,
+            unbonding,
+            callbacks,
+        } => set_pause_state(deps, info, bonding, unbonding, callbacks),
+
+        _ => return Err(ContractError::Never {}),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    assets: Vec<AssetConfigUpdate>,
+    ratio_source: Option<RatioSource>,
+    ratio_tolerance: Option<Decimal>,
+    refund_excess: Option<bool>,
+    swap_contract: Option<String>,
+    id_validation: Option<IdValidation>,
+    bond_timeout: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut event = Event::new("update_config").add_attribute("method", "update_config");
+
+    for update in assets {
+        let asset = match config
+            .assets
+            .iter_mut()
+            .find(|asset| asset.denom == update.denom)
+        {
+            None => {
+                return Err(ContractError::InvalidDenom {
+                    denom: update.denom,
+                })
+            }
+            Some(asset) => asset,
+        };
+
+        // a router can only be swapped once it has no operation in flight, otherwise its callbacks
+        // would be rejected and the funds it holds for the handler would be stuck
+        if let Some(router) = update.router {
+            let router = deps.api.addr_validate(&router)?;
+            if router != asset.router {
+                if router_in_use(deps.storage, &asset.denom)? {
+                    return Err(ContractError::RouterHasPendingOperations {
+                        denom: asset.denom.to_owned(),
+                    });
+                }
+                event = event.add_attribute(format!("router_{}", asset.denom), router.as_str());
+                asset.router = router;
+            }
+        }
+
+        if let Some(lock_period) = update.lock_period {
+            asset.lock_period = lock_period;
+            event = event.add_attribute(
+                format!("lock_period_{}", asset.denom),
+                lock_period.to_string(),
+            );
+        }
+
+        if let Some(ratio) = update.ratio {
+            asset.ratio = ratio;
+            event = event.add_attribute(format!("ratio_{}", asset.denom), ratio.to_string());
+        }
+    }
+
+    if let Some(ratio_source) = ratio_source {
+        config.ratio_source = ratio_source;
+        event = event.add_attribute("ratio_source", format!("{:?}", config.ratio_source));
+    }
+
+    if let Some(ratio_tolerance) = ratio_tolerance {
+        config.ratio_tolerance = ratio_tolerance;
+        event = event.add_attribute("ratio_tolerance", ratio_tolerance.to_string());
+    }
+
+    if let Some(refund_excess) = refund_excess {
+        config.refund_excess = refund_excess;
+        event = event.add_attribute("refund_excess", refund_excess.to_string());
+    }
+
+    if let Some(swap_contract) = swap_contract {
+        let swap_contract = deps.api.addr_validate(&swap_contract)?;
+        event = event.add_attribute("swap_contract", swap_contract.as_str());
+        config.swap_contract = Some(swap_contract);
+    }
+
+    if let Some(id_validation) = id_validation {
+        config.id_validation = id_validation;
+        event = event.add_attribute("id_validation", format!("{:?}", config.id_validation));
+    }
+
+    if let Some(bond_timeout) = bond_timeout {
+        config.bond_timeout = bond_timeout;
+        event = event.add_attribute("bond_timeout", bond_timeout.to_string());
+    }
+
+    // routers must stay distinct so callbacks can be attributed to a denom
+    config.validate(deps.api)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    return Ok(Response::new().add_event(event));
+}
+
+fn propose_new_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_admin: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // the proposed admin only takes over once it accepts, so a mistyped address cannot lock the contract
+    let new_admin = deps.api.addr_validate(&new_admin)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::AdminTransferExpired {});
+    }
+
+    // a new proposal replaces any previous one
+    PENDING_ADMIN.save(
+        deps.storage,
+        &PendingAdmin {
+            new_admin: new_admin.clone(),
+            expires,
+        },
+    )?;
+
+    return Ok(Response::new().add_event(
+        Event::new("propose_new_admin")
+            .add_attribute("method", "propose_new_admin")
+            .add_attribute("admin", info.sender)
+            .add_attribute("new_admin", new_admin)
+            .add_attribute("expires", expires.to_string()),
+    ));
+}
+
+fn accept_admin(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let pending_admin = match PENDING_ADMIN.may_load(deps.storage)? {
+        None => return Err(ContractError::NoPendingAdminTransfer {}),
+        Some(pending_admin) => pending_admin,
+    };
+
+    if info.sender != pending_admin.new_admin {
+        return Err(ContractError::Unauthorized {});
+    } else if pending_admin.expires.is_expired(&env.block) {
+        return Err(ContractError::AdminTransferExpired {});
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    let previous_admin = config.admin;
+    config.admin = pending_admin.new_admin;
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    return Ok(Response::new().add_event(
+        Event::new("accept_admin")
+            .add_attribute("method", "accept_admin")
+            .add_attribute("previous_admin", previous_admin)
+            .add_attribute("admin", config.admin),
+    ));
+}
+
+fn cancel_admin_transfer(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if PENDING_ADMIN.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingAdminTransfer {});
+    }
+    PENDING_ADMIN.remove(deps.storage);
+
+    return Ok(Response::new().add_event(
+        Event::new("cancel_admin_transfer")
+            .add_attribute("method", "cancel_admin_transfer")
+            .add_attribute("admin", info.sender),
+    ));
+}
+
+fn set_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.guardian = match guardian {
+        None => None,
+        Some(guardian) => Some(deps.api.addr_validate(&guardian)?),
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    return Ok(Response::new().add_event(
+        Event::new("set_guardian")
+            .add_attribute("method", "set_guardian")
+            .add_attribute(
+                "guardian",
+                config
+                    .guardian
+                    .map(|guardian| guardian.into_string())
+                    .unwrap_or_default(),
+            ),
+    ));
+}
+
+fn set_pause_state(
+    deps: DepsMut,
+    info: MessageInfo,
+    bonding: Option<bool>,
+    unbonding: Option<bool>,
+    callbacks: Option<bool>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let is_admin = info.sender == config.admin;
+    let is_guardian = config.guardian.as_ref() == Some(&info.sender);
+
+    // the guardian can only pause, lifting a pause is left to the admin
+    let unpauses = [bonding, unbonding, callbacks].contains(&Some(false));
+    let is_allowed = is_admin || (is_guardian && !unpauses);
+    if !is_allowed {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut pause_state = PAUSE_STATE.may_load(deps.storage)?.unwrap_or_default();
+    if let Some(bonding) = bonding {
+        pause_state.bonding = bonding;
+    }
+    if let Some(unbonding) = unbonding {
+        pause_state.unbonding = unbonding;
+    }
+    if let Some(callbacks) = callbacks {
+        pause_state.callbacks = callbacks;
+    }
+    PAUSE_STATE.save(deps.storage, &pause_state)?;
+
+    return Ok(Response::new().add_event(
+        Event::new("set_pause_state")
+            .add_attribute("method", "set_pause_state")
+            .add_attribute("caller", info.sender)
+            .add_attribute("bonding", pause_state.bonding.to_string())
+            .add_attribute("unbonding", pause_state.unbonding.to_string())
+            .add_attribute("callbacks", pause_state.callbacks.to_string()),
+    ));
+}
+
+/// Check if any position still holds shares of the router or waits on it for the denom
+fn router_in_use(storage: &dyn Storage, denom: &str) -> StdResult<bool> {
+    let totals = TOTALS.may_load(storage)?.unwrap_or_default();
+    return Ok(!totals.sent_to_bond.get(denom).is_zero()
+        || !totals.bonded.get(denom).is_zero()
+        || !totals.unconfirmed_unbonding.get(denom).is_zero()
+        || !totals.unbonding.get(denom).is_zero()
+        || !totals.sent_for_unbond.get(denom).is_zero());
+}
@@ -1,9 +1,22 @@
-use cosmwasm_std::{BankMsg, DepsMut, Env, Event, MessageInfo, Response, Uint128};
+use cosmwasm_std::{
+    coin, Addr, BankMsg, CosmosMsg, Decimal, DepsMut, Env, Event, MessageInfo, Order, Response,
+    Storage, Uint128, WasmMsg,
+};
 
 use crate::{
-    msg::{BondResponse, Callback, StartUnbondResponse, UnbondResponse},
-    state::{BOND_STATUS_TRACKER, CONFIG, ID_TO_ADDRESS_TRACKER},
-    typing::{BondStatus, Config, UnbondingElement},
+    contract_execute::{check_id, close_position_if_empty, coins_to_string},
+    contract_reply::{rollback_operation, SWAP_REPLY_ID},
+    msg::{
+        BondResponse, Callback, CallbackMsg, LegacyCallback, ReturnBondResponse,
+        StartUnbondResponse, UnbondResponse,
+    },
+    state::{
+        id_to_address_tracker, load_pending_operation, pending_operations, update_bond_status,
+        BOND_STATUS_TRACKER, CONFIG, EXCHANGE_RATES, NEXT_REPLY_ID, PAUSE_STATE,
+    },
+    typing::{
+        AssetConfig, BondStatus, ExchangeRate, OperationKind, PendingOperation, UnbondingElement,
+    },
     ContractError,
 };
 
@@ -11,101 +24,187 @@ pub fn route_callback(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    msg: Callback,
+    msg: CallbackMsg,
 ) -> Result<Response, ContractError> {
     // check if caller is allowed
     let config = CONFIG.load(deps.storage)?;
 
-    if !config.is_valid_callback_caller(&info.sender) {
-        return Err(ContractError::Unauthorized {});
+    let asset = match config.get_asset_by_router(&info.sender) {
+        None => return Err(ContractError::Unauthorized {}),
+        Some(asset) => asset.to_owned(),
+    };
+
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .callbacks
+    {
+        return Err(ContractError::Paused {
+            operation: "callbacks".into(),
+        });
     }
 
+    let msg = match msg {
+        CallbackMsg::Tagged(callback) => callback,
+        CallbackMsg::Legacy(LegacyCallback::BondResponse(response)) => {
+            Callback::BondResponse(response)
+        }
+        // only the completion of an unbonding sends funds back
+        CallbackMsg::Legacy(LegacyCallback::UnbondResponse(response)) => {
+            if info.funds.is_empty() {
+                Callback::StartUnbondResponse(StartUnbondResponse {
+                    unbond_id: response.unbond_id,
+                    nonce: response.nonce,
+                })
+            } else {
+                Callback::UnbondResponse(response)
+            }
+        }
+    };
+
+    let id = match &msg {
+        Callback::BondResponse(response) => &response.bond_id,
+        Callback::StartUnbondResponse(response) => &response.unbond_id,
+        Callback::UnbondResponse(response) => &response.unbond_id,
+        Callback::ReturnBondResponse(response) => &response.bond_id,
+        Callback::BondFailed(failure) => &failure.bond_id,
+        Callback::StartUnbondFailed(failure) => &failure.unbond_id,
+        Callback::UnbondFailed(failure) => &failure.unbond_id,
+    };
+    check_id(deps.storage, &config, id)?;
+
     match msg {
-        Callback::BondResponse(response) => bond_response(deps, info, config, response),
+        Callback::BondResponse(response) => bond_response(deps, env, asset, response),
         Callback::StartUnbondResponse(response) => {
-            start_unbond_response(deps, env, info, config, response)
+            start_unbond_response(deps, env, asset, response)
         }
-        Callback::UnbondResponse(response) => unbond_response(deps, info, config, response),
+        Callback::UnbondResponse(response) => unbond_response(deps, info, asset, response),
+        Callback::ReturnBondResponse(response) => return_bond_response(deps, info, asset, response),
+        Callback::BondFailed(failure) => operation_failed(
+            deps,
+            info,
+            asset,
+            failure.bond_id,
+            OperationKind::Bond,
+            failure.nonce,
+            failure.reason,
+        ),
+        Callback::StartUnbondFailed(failure) => operation_failed(
+            deps,
+            info,
+            asset,
+            failure.unbond_id,
+            OperationKind::StartUnbond,
+            failure.nonce,
+            failure.reason,
+        ),
+        Callback::UnbondFailed(failure) => operation_failed(
+            deps,
+            info,
+            asset,
+            failure.unbond_id,
+            OperationKind::Unbond,
+            failure.nonce,
+            failure.reason,
+        ),
     }
 }
 
 fn bond_response(
     deps: DepsMut,
-    info: MessageInfo,
-    config: Config,
+    env: Env,
+    asset: AssetConfig,
     response: BondResponse,
 ) -> Result<Response, ContractError> {
+    // the router must be answering an operation still waiting on it, so replays are rejected
+    let operation = settle_operation(
+        deps.storage,
+        &response.bond_id,
+        &asset.router,
+        OperationKind::Bond,
+        response.nonce,
+    )?;
+
     // bonding is successful, update the state
-    BOND_STATUS_TRACKER
-        .update(
-            deps.storage,
-            response.bond_id.clone(), // info.sender.clone()),
-            |bond_status_data| -> Result<BondStatus, ContractError> {
-                let mut bond_status = bond_status_data.unwrap_or_default();
-
-                if info.sender == config.router_denom_1 {
-                    bond_status.sent_to_bond.denom_1 -= response.share_amount;
-                    bond_status.bonded.denom_1 += response.share_amount;
-                } else {
-                    bond_status.sent_to_bond.denom_2 -= response.share_amount;
-                    bond_status.bonded.denom_2 += response.share_amount;
-                }
+    update_bond_status(
+        deps.storage,
+        response.bond_id.clone(), // info.sender.clone()),
+        |bond_status_data| -> Result<BondStatus, ContractError> {
+            let mut bond_status = bond_status_data.unwrap_or_default();
 
-                return Ok(bond_status);
-            },
-        )
-        .unwrap();
+            // the tokens sent are now held as shares
+            bond_status
+                .sent_to_bond
+                .sub(&asset.denom, operation.amount)?;
+            bond_status
+                .bonded
+                .add(&asset.denom, response.share_amount)?;
+
+            return Ok(bond_status);
+        },
+    )?;
+
+    let mut exchange_rate = EXCHANGE_RATES
+        .may_load(deps.storage, asset.router.clone())?
+        .unwrap_or(ExchangeRate {
+            denom: asset.denom.to_owned(),
+            tokens: Uint128::zero(),
+            shares: Uint128::zero(),
+            rate: Decimal::one(),
+            updated_at: env.block.time,
+        });
+    // share amounts come from the router, so the totals and the rate are checked
+    exchange_rate.tokens = exchange_rate.tokens.checked_add(operation.amount)?;
+    exchange_rate.shares = exchange_rate.shares.checked_add(response.share_amount)?;
+    if !response.share_amount.is_zero() {
+        exchange_rate.rate = Decimal::checked_from_ratio(operation.amount, response.share_amount)?;
+    }
+    exchange_rate.updated_at = env.block.time;
+    EXCHANGE_RATES.save(deps.storage, asset.router.clone(), &exchange_rate)?;
 
     return Ok(Response::new().add_event(
         Event::new("callback_bond")
             .add_attribute("method", "bond_response")
             .add_attribute("id", response.bond_id)
-            .add_attribute(
-                "denom",
-                if info.sender == config.router_denom_1 {
-                    config.denom_1
-                } else {
-                    config.denom_2
-                },
-            )
-            .add_attribute("value", response.share_amount),
+            .add_attribute("denom", &asset.denom)
+            .add_attribute("value", response.share_amount)
+            .add_attribute("tokens", operation.amount)
+            .add_attribute("rate", exchange_rate.rate.to_string()),
     ));
 }
 
 fn start_unbond_response(
     deps: DepsMut,
     env: Env,
-    info: MessageInfo,
-    config: Config,
+    asset: AssetConfig,
     response: StartUnbondResponse,
 ) -> Result<Response, ContractError> {
-    BOND_STATUS_TRACKER
-        .update(
-            deps.storage,
-            response.unbond_id.clone(), // info.sender.clone()),
-            |bond_status_data| -> Result<BondStatus, ContractError> {
-                let mut bond_status = bond_status_data.unwrap_or_default();
-
-                if info.sender == config.router_denom_1 {
-                    bond_status.unbonding.push(UnbondingElement {
-                        denom: config.denom_1,
-                        value: bond_status.unconfirmed_unbonding.denom_1,
-                        unbonding_start_time: env.block.time,
-                    });
-                    bond_status.unconfirmed_unbonding.denom_1 = Uint128::zero();
-                } else {
-                    bond_status.unbonding.push(UnbondingElement {
-                        denom: config.denom_2,
-                        value: bond_status.unconfirmed_unbonding.denom_2,
-                        unbonding_start_time: env.block.time,
-                    });
-                    bond_status.unconfirmed_unbonding.denom_2 = Uint128::zero();
-                }
+    settle_operation(
+        deps.storage,
+        &response.unbond_id,
+        &asset.router,
+        OperationKind::StartUnbond,
+        response.nonce,
+    )?;
+
+    update_bond_status(
+        deps.storage,
+        response.unbond_id.clone(), // info.sender.clone()),
+        |bond_status_data| -> Result<BondStatus, ContractError> {
+            let mut bond_status = bond_status_data.unwrap_or_default();
+
+            bond_status.unbonding.push(UnbondingElement {
+                denom: asset.denom.to_owned(),
+                value: bond_status.unconfirmed_unbonding.get(&asset.denom),
+                unbonding_start_time: env.block.time,
+            });
+            bond_status
+                .unconfirmed_unbonding
+                .set(&asset.denom, Uint128::zero());
 
-                return Ok(bond_status);
-            },
-        )
-        .unwrap();
+            return Ok(bond_status);
+        },
+    )?;
 
     return Ok(Response::new().add_event(
         Event::new("callback_start_unbond")
@@ -117,40 +216,289 @@ fn start_unbond_response(
 fn unbond_response(
     deps: DepsMut,
     info: MessageInfo,
-    config: Config,
+    asset: AssetConfig,
     response: UnbondResponse,
 ) -> Result<Response, ContractError> {
     // get address associated with the ID
-    let target_addr = ID_TO_ADDRESS_TRACKER.load(deps.storage, response.unbond_id.clone())?;
+    let owner = id_to_address_tracker().load(deps.storage, response.unbond_id.clone())?;
+
+    // the router returns its own denom, and no more than what is waiting on it
+    let amount = match info.funds.as_slice() {
+        [funds] if funds.denom == asset.denom && !funds.amount.is_zero() => funds.amount,
+        _ => {
+            return Err(ContractError::InvalidUnbondFunds {
+                denom: asset.denom.to_owned(),
+            })
+        }
+    };
+    let mut operation = find_operation(
+        deps.storage,
+        &response.unbond_id,
+        &asset.router,
+        OperationKind::Unbond,
+        response.nonce,
+    )?;
+    let sent_for_unbond = BOND_STATUS_TRACKER
+        .load(deps.storage, response.unbond_id.clone())?
+        .sent_for_unbond
+        .get(&asset.denom);
+    // v0.1 routers do not report shares, their funds and shares are 1:1
+    let shares = response.share_amount.unwrap_or(amount);
+    let expected = operation.amount.min(sent_for_unbond);
+    if shares > expected {
+        return Err(ContractError::UnbondFundsTooHigh {
+            amount: coin(shares.u128(), &asset.denom),
+            expected: coin(expected.u128(), &asset.denom),
+        });
+    }
+
+    // a short payment is a partial settlement, the operation waits for the rest. Operations
+    // recorded before destinations were tracked send their funds to the owner
+    let withdrawal = operation.withdrawal.clone();
+    if shares == operation.amount {
+        pending_operations().remove(deps.storage, operation.key())?;
+    } else {
+        operation.amount -= shares;
+        pending_operations().save(deps.storage, operation.key(), &operation)?;
+    }
+
+    let (target_addr, forward_msg) = match withdrawal {
+        None => (owner, None),
+        Some(withdrawal) => (withdrawal.recipient.unwrap_or(owner), withdrawal.msg),
+    };
 
     // mark the value as received
-    BOND_STATUS_TRACKER.update(
+    let bond_status = update_bond_status(
         deps.storage,
         response.unbond_id.clone(),
         |bond_status| -> Result<BondStatus, ContractError> {
             let mut bond_status = bond_status.unwrap();
 
-            if info.sender == config.router_denom_1 {
-                bond_status.sent_for_unbond.denom_1 -= info.funds[0].amount;
-            } else {
-                // if info.sender == config.denom_2 {
-                bond_status.sent_for_unbond.denom_2 -= info.funds[0].amount;
-            }
+            bond_status.sent_for_unbond.sub(&asset.denom, shares)?;
 
             return Ok(bond_status);
         },
     )?;
 
-    // and send message funds to it
-    return Ok(Response::new()
-        .add_message(BankMsg::Send {
+    // and send message funds to it, through the forwarded message if any
+    let msg: CosmosMsg = match forward_msg {
+        None => BankMsg::Send {
             to_address: target_addr.clone().into_string(),
             amount: info.funds,
+        }
+        .into(),
+        Some(msg) => WasmMsg::Execute {
+            contract_addr: target_addr.clone().into_string(),
+            msg,
+            funds: info.funds,
+        }
+        .into(),
+    };
+
+    let mut res = Response::new().add_message(msg).add_event(
+        Event::new("callback_unbond")
+            .add_attribute("method", "unbond_response")
+            .add_attribute("id", &response.unbond_id)
+            .add_attribute("beneficiary", target_addr),
+    );
+
+    // the last funds of the position have been withdrawn, free the id
+    if let Some(event) = close_position_if_empty(deps.storage, &response.unbond_id, &bond_status)? {
+        res = res.add_event(event);
+    }
+
+    return Ok(res);
+}
+
+fn return_bond_response(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetConfig,
+    response: ReturnBondResponse,
+) -> Result<Response, ContractError> {
+    let owner = id_to_address_tracker().load(deps.storage, response.bond_id.clone())?;
+
+    // only the denom of the router can be returned, and only for a bond it did not confirm
+    let amount = match info.funds.as_slice() {
+        [funds] if funds.denom == asset.denom => funds.amount,
+        _ => {
+            return Err(ContractError::InvalidDenom {
+                denom: asset.denom.to_owned(),
+            })
+        }
+    };
+
+    // the whole bond comes back at once, anything else would leave sent_to_bond out of sync
+    let operation = find_operation(
+        deps.storage,
+        &response.bond_id,
+        &asset.router,
+        OperationKind::Bond,
+        response.nonce,
+    )?;
+    if amount != operation.amount {
+        return Err(ContractError::InvalidReturnedAmount {
+            amount: coin(amount.u128(), &asset.denom),
+            expected: coin(operation.amount.u128(), &asset.denom),
+        });
+    }
+    pending_operations().remove(deps.storage, operation.key())?;
+
+    let bond_status = update_bond_status(
+        deps.storage,
+        response.bond_id.clone(),
+        |bond_status| -> Result<BondStatus, ContractError> {
+            let mut bond_status = bond_status.unwrap_or_default();
+
+            bond_status
+                .sent_to_bond
+                .sub(&asset.denom, operation.amount)?;
+
+            return Ok(bond_status);
+        },
+    )?;
+
+    let mut res = Response::new()
+        .add_message(BankMsg::Send {
+            to_address: owner.to_string(),
+            amount: info.funds,
         })
         .add_event(
-            Event::new("callback_unbond")
-                .add_attribute("method", "unbond_response")
-                .add_attribute("id", response.unbond_id)
-                .add_attribute("beneficiary", target_addr),
-        ));
+            Event::new("callback_return_bond")
+                .add_attribute("method", "return_bond_response")
+                .add_attribute("id", &response.bond_id)
+                .add_attribute("denom", &asset.denom)
+                .add_attribute("value", amount)
+                .add_attribute("beneficiary", owner),
+        );
+
+    if let Some(event) = close_position_if_empty(deps.storage, &response.bond_id, &bond_status)? {
+        res = res.add_event(event);
+    }
+
+    return Ok(res);
+}
+
+/// The router could not carry out an operation. Its amount goes back to the previous stage of the
+/// position, and the funds the router returned are sent to the owner
+fn operation_failed(
+    deps: DepsMut,
+    info: MessageInfo,
+    asset: AssetConfig,
+    id: String,
+    kind: OperationKind,
+    nonce: Option<u64>,
+    reason: String,
+) -> Result<Response, ContractError> {
+    if let Some(coin) = info.funds.iter().find(|coin| coin.denom != asset.denom) {
+        return Err(ContractError::InvalidDenom {
+            denom: coin.denom.to_owned(),
+        });
+    }
+
+    // a failed bond returns the whole deposit, while the shares of a failed unbonding are
+    // rolled back so paying them out as well would count them twice
+    let operation = find_operation(deps.storage, &id, &asset.router, kind, nonce)?;
+    if operation.kind == OperationKind::Bond {
+        let amount = info
+            .funds
+            .iter()
+            .fold(Uint128::zero(), |total, coin| total + coin.amount);
+        if amount != operation.amount {
+            return Err(ContractError::InvalidReturnedAmount {
+                amount: coin(amount.u128(), &asset.denom),
+                expected: coin(operation.amount.u128(), &asset.denom),
+            });
+        }
+    } else if !info.funds.is_empty() {
+        return Err(ContractError::UnexpectedFunds {});
+    }
+    pending_operations().remove(deps.storage, operation.key())?;
+    let owner = id_to_address_tracker().load(deps.storage, id.clone())?;
+
+    let bond_status = rollback_operation(deps.storage, &operation)?;
+
+    let mut res = Response::new();
+    if !info.funds.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: owner.to_string(),
+            amount: info.funds.clone(),
+        });
+    }
+    res = res.add_event(
+        Event::new("callback_failed")
+            .add_attribute("method", "operation_failed")
+            .add_attribute("id", &id)
+            .add_attribute("kind", format!("{:?}", operation.kind))
+            .add_attribute(
+                "amount",
+                coin(operation.amount.u128(), &asset.denom).to_string(),
+            )
+            .add_attribute("returned", coins_to_string(&info.funds))
+            .add_attribute("reason", reason),
+    );
+
+    if let Some(event) = close_position_if_empty(deps.storage, &id, &bond_status)? {
+        res = res.add_event(event);
+    }
+
+    return Ok(res);
+}
+
+/// Remove the operation of the kind sent to the router for the id, now that the router confirmed
+/// it
+fn settle_operation(
+    storage: &mut dyn Storage,
+    id: &str,
+    router: &Addr,
+    kind: OperationKind,
+    nonce: Option<u64>,
+) -> Result<PendingOperation, ContractError> {
+    let operation = find_operation(storage, id, router, kind, nonce)?;
+    pending_operations().remove(storage, operation.key())?;
+
+    return Ok(operation);
+}
+
+/// Load the operation of the kind sent to the router for the id that a callback answers.
+/// Callbacks without a nonce answer the oldest matching operation
+fn find_operation(
+    storage: &dyn Storage,
+    id: &str,
+    router: &Addr,
+    kind: OperationKind,
+    nonce: Option<u64>,
+) -> Result<PendingOperation, ContractError> {
+    let operation = match nonce {
+        Some(nonce) => match load_pending_operation(storage, nonce)? {
+            Some(operation)
+                if operation.id == id && operation.router == *router && operation.kind == kind =>
+            {
+                operation
+            }
+            Some(_) => return Err(ContractError::UnknownOperation { nonce }),
+            None => {
+                // every nonce below the next one was sent, and its operation is done
+                let next_nonce = NEXT_REPLY_ID.may_load(storage)?.unwrap_or_default();
+                if nonce > SWAP_REPLY_ID && nonce < next_nonce {
+                    return Err(ContractError::OperationAlreadySettled { nonce });
+                }
+                return Err(ContractError::UnknownOperation { nonce });
+            }
+        },
+        None => match pending_operations()
+            .prefix((id.to_owned(), router.clone()))
+            .range(storage, None, None, Order::Ascending)
+            .find(|item| match item {
+                Ok((_, operation)) => operation.kind == kind,
+                Err(_) => true,
+            })
+            .transpose()?
+        {
+            None => return Err(ContractError::NoPendingOperation {}),
+            Some((_, operation)) => operation,
+        },
+    };
+
+    return Ok(operation);
 }
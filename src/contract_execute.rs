@@ -1,9 +1,23 @@
-use cosmwasm_std::{to_binary, Coin, DepsMut, Env, Event, MessageInfo, Response, Uint128, WasmMsg};
+use cosmwasm_std::{
+    coin, to_binary, Addr, BankMsg, Binary, Coin, Decimal, Decimal256, Deps, DepsMut, Env, Event,
+    MessageInfo, Order, Response, StdError, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+};
+use cw_utils::Expiration;
 
 use crate::{
-    msg::{ExecuteMsg, ExternalExecuteMsg},
-    state::{BOND_STATUS_TRACKER, CONFIG, ID_TO_ADDRESS_TRACKER},
-    typing::{BondStatus, UnbondingElement},
+    contract_reply::SWAP_REPLY_ID,
+    msg::{
+        BondData, ExecuteMsg, ExternalExecuteMsg, ExternalQueryMsg, PriceResponse,
+        SimulationResponse,
+    },
+    state::{
+        id_to_address_tracker, pending_operations, update_bond_status, APPROVALS,
+        BOND_STATUS_TRACKER, CONFIG, NEXT_ID, NEXT_REPLY_ID, OPERATORS, PAUSE_STATE, PENDING_SWAP,
+    },
+    typing::{
+        Approval, AssetConfig, BondStatus, BondStatusData, Config, OperationKind, PendingOperation,
+        PendingSwap, RatioSource, UnbondingElement, Withdrawal,
+    },
     ContractError,
 };
 
@@ -14,256 +28,749 @@ pub fn route_execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Bond { id } => bond(deps, info, id),
-        ExecuteMsg::StartUnbond { id, share_amount } => {
-            start_unbond(deps, env, info, id, share_amount)
+        ExecuteMsg::Bond { id } => bond(deps, env, info, id),
+        ExecuteMsg::BondSingleSided { id, min_out } => {
+            bond_single_sided(deps, env, info, id, min_out)
         }
-        ExecuteMsg::Unbond { id, share_amount } => unbond(deps, env, info, id, share_amount),
+        ExecuteMsg::StartUnbond {
+            id,
+            share_amount,
+            share_amounts,
+        } => start_unbond(deps, env, info, id, share_amount, share_amounts),
+        ExecuteMsg::Unbond {
+            id,
+            share_amount,
+            share_amounts,
+            recipient,
+            msg,
+        } => unbond(
+            deps,
+            env,
+            info,
+            id,
+            share_amount,
+            share_amounts,
+            recipient,
+            msg,
+        ),
+        ExecuteMsg::TransferId { id, new_owner } => transfer_id(deps, info, id, new_owner),
+        ExecuteMsg::ReclaimStaleBond { id } => reclaim_stale_bond(deps, env, info, id),
+        ExecuteMsg::CloseId { id } => close_id(deps, info, id),
+        ExecuteMsg::Approve {
+            id,
+            operator,
+            expires,
+            allow_custom_recipient,
+        } => approve(
+            deps,
+            env,
+            info,
+            id,
+            operator,
+            expires,
+            allow_custom_recipient,
+        ),
+        ExecuteMsg::Revoke { id, operator } => revoke(deps, info, id, operator),
+        ExecuteMsg::ApproveAll {
+            operator,
+            expires,
+            allow_custom_recipient,
+        } => approve_all(deps, env, info, operator, expires, allow_custom_recipient),
+        ExecuteMsg::RevokeAll { operator } => revoke_all(deps, info, operator),
 
         _ => return Err(ContractError::Never {}),
     }
 }
 
-fn bond(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
-    // start by checking if deposits are valid
-    if info.funds.len() != 2 {
-        return Err(ContractError::MismatchAmountDenoms {
-            req_amount_denoms: 2,
+fn bond(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<String>,
+) -> Result<Response, ContractError> {
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .bonding
+    {
+        return Err(ContractError::Paused {
+            operation: "bonding".into(),
         });
-    } else if info.funds[0].amount != info.funds[1].amount {
-        return Err(ContractError::FundsAmountNotEqual {});
-    } else if info.funds[0].denom == info.funds[1].denom {
-        return Err(ContractError::FundsDenomAreSame {});
     }
 
-    // get config to check if denoms sent are allowed
     let config = CONFIG.load(deps.storage)?;
-    if !config.is_valid_denom(&info.funds[0].denom) {
-        return Err(ContractError::InvalidDenom {
-            denom: info.funds[0].denom.to_owned(),
+    let id = match id {
+        None => generate_id(deps.storage)?,
+        Some(id) => {
+            check_id(deps.storage, &config, &id)?;
+            id
+        }
+    };
+
+    let refund_excess = config.refund_excess;
+    return bond_funds(
+        deps,
+        &env,
+        config,
+        info.sender,
+        id,
+        info.funds,
+        refund_excess,
+    );
+}
+
+/// Check an id against the configured rules. Ids already allocated are accepted, so positions
+/// stay reachable when the rules change
+pub(crate) fn check_id(
+    storage: &dyn Storage,
+    config: &Config,
+    id: &str,
+) -> Result<(), ContractError> {
+    if id_to_address_tracker().has(storage, id.to_owned()) {
+        return Ok(());
+    }
+
+    return config.id_validation.validate(id);
+}
+
+/// Allocate the next free id from the counter, skipping ids already chosen by users
+fn generate_id(storage: &mut dyn Storage) -> StdResult<String> {
+    let mut next_id = NEXT_ID.may_load(storage)?.unwrap_or(1);
+    while id_to_address_tracker().has(storage, next_id.to_string()) {
+        next_id += 1;
+    }
+    NEXT_ID.save(storage, &(next_id + 1))?;
+
+    return Ok(next_id.to_string());
+}
+
+/// Allocate the nonce of an operation sent to a router, which is also the reply id of its message
+pub(crate) fn next_nonce(storage: &mut dyn Storage) -> StdResult<u64> {
+    // reply ids of the routers start right after the one of the swap
+    let nonce = NEXT_REPLY_ID
+        .may_load(storage)?
+        .unwrap_or(SWAP_REPLY_ID + 1);
+    NEXT_REPLY_ID.save(storage, &(nonce + 1))?;
+
+    return Ok(nonce);
+}
+
+/// Send the message built with the nonce of the operation to the router of the asset as a
+/// submessage, recording the operation under its nonce until the router confirms it with a callback
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn dispatch_to_router(
+    storage: &mut dyn Storage,
+    env: &Env,
+    id: &str,
+    asset: &AssetConfig,
+    kind: OperationKind,
+    amount: Uint128,
+    withdrawal: Option<Withdrawal>,
+    build_msg: impl FnOnce(u64) -> StdResult<WasmMsg>,
+) -> StdResult<SubMsg> {
+    let nonce = next_nonce(storage)?;
+
+    let operation = PendingOperation {
+        nonce,
+        id: id.to_owned(),
+        router: asset.router.clone(),
+        denom: asset.denom.to_owned(),
+        kind,
+        amount,
+        dispatched_at: env.block.time,
+        accepted: false,
+        reclaimed: false,
+        withdrawal,
+    };
+    pending_operations().save(storage, operation.key(), &operation)?;
+
+    return Ok(SubMsg::reply_always(build_msg(nonce)?, nonce));
+}
+
+/// Register the deposit of `funds` for the id owned by `owner`, and send them to the routers.
+/// With `refund_excess`, only the balanced portion of the funds is bonded and the rest is sent
+/// back to the owner.
+pub(crate) fn bond_funds(
+    deps: DepsMut,
+    env: &Env,
+    config: Config,
+    owner: Addr,
+    id: String,
+    funds: Vec<Coin>,
+    refund_excess: bool,
+) -> Result<Response, ContractError> {
+    // start by checking if deposits are valid: one coin per configured denom, following the ratios
+    if funds.len() != config.assets.len() {
+        return Err(ContractError::MismatchAmountDenoms {
+            req_amount_denoms: config.assets.len() as u32,
         });
-    } else if !config.is_valid_denom(&info.funds[1].denom) {
+    }
+
+    for (index, coin) in funds.iter().enumerate() {
+        if funds[index + 1..]
+            .iter()
+            .any(|other| other.denom == coin.denom)
+        {
+            return Err(ContractError::FundsDenomAreSame {});
+        }
+    }
+
+    if let Some(coin) = funds
+        .iter()
+        .find(|coin| !config.is_valid_denom(&coin.denom))
+    {
         return Err(ContractError::InvalidDenom {
-            denom: info.funds[1].denom.to_owned(),
+            denom: coin.denom.to_owned(),
         });
     }
 
+    // either the whole deposit follows the ratios, or only its balanced portion is bonded
+    let (funds, refund) = if refund_excess {
+        balance_deposit(deps.as_ref(), &config, &funds)?
+    } else {
+        check_deposit_ratio(deps.as_ref(), &config, &funds)?;
+        (funds, vec![])
+    };
+
     // check if the ID is available, or if caller is owner of the id
-    match ID_TO_ADDRESS_TRACKER.load(deps.storage, id.clone()) {
-        Ok(owner) => {
-            if owner != info.sender {
+    match id_to_address_tracker().load(deps.storage, id.clone()) {
+        Ok(id_owner) => {
+            if id_owner != owner {
                 return Err(ContractError::Unauthorized {});
             }
         }
         Err(_) => {
             // register id to caller
-            ID_TO_ADDRESS_TRACKER.save(deps.storage, id.clone(), &info.sender)?;
+            id_to_address_tracker().save(deps.storage, id.clone(), &owner)?;
         }
     }
 
-    BOND_STATUS_TRACKER.update(
+    update_bond_status(
         deps.storage,
         id.clone(),
         |bond_status| -> Result<BondStatus, ContractError> {
             let mut bond_status = bond_status.unwrap_or_default();
-            bond_status.sent_to_bond.denom_1 += info.funds[0].amount;
-            bond_status.sent_to_bond.denom_2 += info.funds[0].amount;
+            for coin in &funds {
+                bond_status.sent_to_bond.add(&coin.denom, coin.amount)?;
+            }
 
             return Ok(bond_status);
         },
     )?;
 
     // deposit has been written to storage, now can create the funds messages towards the routers
-    let msg_router_1 = WasmMsg::Execute {
-        contract_addr: config.router_denom_1.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnBond { id: id.to_owned() })?,
-        funds: vec![Coin {
-            denom: config.denom_1,
-            amount: info.funds[0].amount,
-        }],
+    let mut msgs: Vec<SubMsg> = vec![];
+    for asset in &config.assets {
+        let amount = funds
+            .iter()
+            .find(|coin| coin.denom == asset.denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+
+        msgs.push(dispatch_to_router(
+            deps.storage,
+            env,
+            &id,
+            asset,
+            OperationKind::Bond,
+            amount,
+            None,
+            |nonce| {
+                return Ok(WasmMsg::Execute {
+                    contract_addr: asset.router.to_string(),
+                    msg: to_binary(&ExternalExecuteMsg::OnBond {
+                        id: id.to_owned(),
+                        nonce,
+                    })?,
+                    funds: vec![coin(amount.u128(), &asset.denom)],
+                });
+            },
+        )?);
+    }
+
+    let mut event = Event::new("bond")
+        .add_attribute("method", "bond")
+        .add_attribute("caller", &owner)
+        .add_attribute("id", &id)
+        .add_attribute("value", funds[0].amount)
+        .add_attribute("funds", coins_to_string(&funds));
+    let mut response = Response::new().add_submessages(msgs);
+    // attribute values cannot be empty, so the refund only shows up when there is one
+    if !refund.is_empty() {
+        event = event.add_attribute("refund", coins_to_string(&refund));
+        response = response.add_message(BankMsg::Send {
+            to_address: owner.to_string(),
+            amount: refund,
+        });
+    }
+
+    // send the messages and emit an event, the id is returned in the data for generated ids
+    return Ok(response
+        .set_data(to_binary(&BondData { id })?)
+        .add_event(event));
+}
+
+fn bond_single_sided(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: Option<String>,
+    min_out: Uint128,
+) -> Result<Response, ContractError> {
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .bonding
+    {
+        return Err(ContractError::Paused {
+            operation: "bonding".into(),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let swap_contract = match &config.swap_contract {
+        None => return Err(ContractError::SwapNotConfigured {}),
+        Some(swap_contract) => swap_contract.to_owned(),
     };
 
-    let msg_router_2 = WasmMsg::Execute {
-        contract_addr: config.router_denom_2.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnBond { id: id.to_owned() })?,
-        funds: vec![Coin {
-            denom: config.denom_2,
-            amount: info.funds[0].amount,
-        }],
+    // half of the deposit is swapped into the other denom, so there must be exactly one other denom
+    if config.assets.len() != 2 {
+        return Err(ContractError::SingleSidedRequiresTwoAssets {});
+    } else if info.funds.len() != 1 {
+        return Err(ContractError::MismatchAmountDenoms {
+            req_amount_denoms: 1,
+        });
+    }
+
+    let deposit = info.funds[0].to_owned();
+    let ask_denom = match config.assets.iter().position(|a| a.denom == deposit.denom) {
+        Some(0) => config.assets[1].denom.to_owned(),
+        Some(_) => config.assets[0].denom.to_owned(),
+        None => {
+            return Err(ContractError::InvalidDenom {
+                denom: deposit.denom,
+            })
+        }
+    };
+
+    let id = match id {
+        None => generate_id(deps.storage)?,
+        Some(id) => {
+            check_id(deps.storage, &config, &id)?;
+            id
+        }
+    };
+
+    // fail early rather than after the swap if the id belongs to someone else
+    if let Some(owner) = id_to_address_tracker().may_load(deps.storage, id.clone())? {
+        if owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+
+    // a single swap can be in flight, the reply consumes it
+    if PENDING_SWAP.may_load(deps.storage)?.is_some() {
+        return Err(ContractError::SwapInProgress {});
+    }
+
+    let kept = coin(deposit.amount.u128() / 2, &deposit.denom);
+    let offer = coin(deposit.amount.u128() - kept.amount.u128(), &deposit.denom);
+
+    let simulation: SimulationResponse = deps.querier.query_wasm_smart(
+        &swap_contract,
+        &ExternalQueryMsg::Simulation {
+            offer: offer.clone(),
+            ask_denom: ask_denom.clone(),
+        },
+    )?;
+    if simulation.return_amount < min_out {
+        return Err(ContractError::SlippageExceeded {
+            min_out,
+            return_amount: simulation.return_amount,
+        });
+    }
+
+    // the swapped amount is the balance increase once the swap is done, see the reply
+    let balance_before = deps
+        .querier
+        .query_balance(&env.contract.address, &ask_denom)?
+        .amount;
+    PENDING_SWAP.save(
+        deps.storage,
+        &PendingSwap {
+            id: id.clone(),
+            owner: info.sender.clone(),
+            kept,
+            ask_denom: ask_denom.clone(),
+            min_out,
+            balance_before,
+        },
+    )?;
+
+    let swap_msg = WasmMsg::Execute {
+        contract_addr: swap_contract.into_string(),
+        msg: to_binary(&ExternalExecuteMsg::Swap {
+            ask_denom: ask_denom.clone(),
+            min_out,
+        })?,
+        funds: vec![offer.clone()],
     };
 
-    // send the messages and emit an event
     return Ok(Response::new()
-        .add_message(msg_router_1)
-        .add_message(msg_router_2)
+        .add_submessage(SubMsg::reply_on_success(swap_msg, SWAP_REPLY_ID))
+        .set_data(to_binary(&BondData { id: id.clone() })?)
         .add_event(
-            Event::new("bond")
-                .add_attribute("method", "bond")
+            Event::new("bond_single_sided")
+                .add_attribute("method", "bond_single_sided")
                 .add_attribute("caller", info.sender)
                 .add_attribute("id", id)
-                .add_attribute("value", info.funds[0].amount),
+                .add_attribute("offer", offer.to_string())
+                .add_attribute("ask_denom", ask_denom)
+                .add_attribute("simulated_return_amount", simulation.return_amount),
         ));
 }
 
+/// Weight of each denom in a balanced deposit: a deposit is balanced when every amount divided
+/// by the weight of its denom gives the same number of units
+fn deposit_weights(deps: Deps, config: &Config) -> Result<Vec<(String, Decimal)>, ContractError> {
+    let mut weights: Vec<(String, Decimal)> = vec![];
+    for asset in &config.assets {
+        let weight = match &config.ratio_source {
+            RatioSource::Static {} => asset.ratio,
+            RatioSource::Oracle { contract } => {
+                // deposits must have the same value, so amounts are inversely proportional to prices
+                let res: PriceResponse = deps.querier.query_wasm_smart(
+                    contract,
+                    &ExternalQueryMsg::Price {
+                        denom: asset.denom.to_owned(),
+                    },
+                )?;
+                if res.price.is_zero() {
+                    return Err(ContractError::InvalidPrice {
+                        denom: asset.denom.to_owned(),
+                    });
+                }
+                Decimal::one().checked_div(res.price)?
+            }
+        };
+        weights.push((asset.denom.to_owned(), weight));
+    }
+
+    return Ok(weights);
+}
+
+/// Convert deposited amounts to units, in the same order as the funds. Units are 256 bits wide
+/// since raw amounts of 18 decimals denoms do not fit in a Decimal
+fn deposit_units(
+    weights: &[(String, Decimal)],
+    funds: &[Coin],
+) -> Result<Vec<Decimal256>, ContractError> {
+    let mut units: Vec<Decimal256> = vec![];
+    for coin in funds {
+        let weight = match weights.iter().find(|(denom, _)| *denom == coin.denom) {
+            None => {
+                return Err(ContractError::InvalidDenom {
+                    denom: coin.denom.to_owned(),
+                })
+            }
+            Some((_, weight)) => *weight,
+        };
+        units.push(Decimal256::checked_from_ratio(coin.amount, 1u128)?.checked_div(weight.into())?);
+    }
+
+    return Ok(units);
+}
+
+/// Check that the deposited amounts follow the configured ratios, within the tolerance
+fn check_deposit_ratio(deps: Deps, config: &Config, funds: &[Coin]) -> Result<(), ContractError> {
+    let weights = deposit_weights(deps, config)?;
+    let units = deposit_units(&weights, funds)?;
+
+    let min = units.iter().min().copied().unwrap_or_default();
+    let max = units.iter().max().copied().unwrap_or_default();
+    if max > min.checked_mul(Decimal256::one() + Decimal256::from(config.ratio_tolerance))? {
+        return Err(ContractError::FundsAmountNotEqual {});
+    }
+
+    return Ok(());
+}
+
+/// Split a deposit between its largest balanced portion, which is bonded, and the excess,
+/// which is refunded. Deposits within the tolerance are bonded in full.
+fn balance_deposit(
+    deps: Deps,
+    config: &Config,
+    funds: &[Coin],
+) -> Result<(Vec<Coin>, Vec<Coin>), ContractError> {
+    let weights = deposit_weights(deps, config)?;
+    let units = deposit_units(&weights, funds)?;
+
+    let min = units.iter().min().copied().unwrap_or_default();
+    let max = units.iter().max().copied().unwrap_or_default();
+    if max <= min.checked_mul(Decimal256::one() + Decimal256::from(config.ratio_tolerance))? {
+        return Ok((funds.to_vec(), vec![]));
+    }
+
+    let mut bonded: Vec<Coin> = vec![];
+    let mut refund: Vec<Coin> = vec![];
+    for coin in funds {
+        let weight = weights
+            .iter()
+            .find(|(denom, _)| *denom == coin.denom)
+            .map(|(_, weight)| *weight)
+            .unwrap_or_default();
+
+        // rounding down keeps the bonded portion within the deposit
+        let amount = std::cmp::min(
+            min.checked_mul(weight.into())?.to_uint_floor(),
+            coin.amount.into(),
+        );
+        let amount = Uint128::try_from(amount).map_err(StdError::from)?;
+        if amount.is_zero() {
+            return Err(ContractError::FundsAmountNotEqual {});
+        }
+
+        bonded.push(Coin {
+            denom: coin.denom.to_owned(),
+            amount,
+        });
+        if amount < coin.amount {
+            refund.push(Coin {
+                denom: coin.denom.to_owned(),
+                amount: coin.amount - amount,
+            });
+        }
+    }
+
+    return Ok((bonded, refund));
+}
+
+/// Resolve the share amounts of an unbonding request to an amount per configured denom
+fn resolve_share_amounts(
+    config: &Config,
+    share_amount: Option<Uint128>,
+    share_amounts: Option<Vec<Coin>>,
+) -> Result<BondStatusData, ContractError> {
+    match (share_amount, share_amounts) {
+        (Some(share_amount), None) => {
+            return Ok(BondStatusData(
+                config
+                    .assets
+                    .iter()
+                    .map(|asset| coin(share_amount.u128(), &asset.denom))
+                    .collect(),
+            ));
+        }
+        (None, Some(share_amounts)) => {
+            let mut resolved = BondStatusData::default();
+            for share in share_amounts {
+                if !config.is_valid_denom(&share.denom) {
+                    return Err(ContractError::InvalidDenom { denom: share.denom });
+                }
+                resolved.add(&share.denom, share.amount)?;
+            }
+
+            // keep the order of the config, denoms not requested are left untouched
+            return Ok(BondStatusData(
+                config
+                    .assets
+                    .iter()
+                    .map(|asset| coin(resolved.get(&asset.denom).u128(), &asset.denom))
+                    .collect(),
+            ));
+        }
+        _ => return Err(ContractError::InvalidShareAmounts {}),
+    }
+}
+
+pub(crate) fn coins_to_string(coins: &[Coin]) -> String {
+    return coins
+        .iter()
+        .map(|coin| coin.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+}
+
 fn start_unbond(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     id: String,
-    share_amount: Uint128,
+    share_amount: Option<Uint128>,
+    share_amounts: Option<Vec<Coin>>,
 ) -> Result<Response, ContractError> {
-    // check if caller is owner of id
-    match ID_TO_ADDRESS_TRACKER.load(deps.storage, id.clone()) {
-        Err(_) => return Err(ContractError::IdNotAllocated {}),
-        Ok(owner) => {
-            if owner != info.sender {
-                return Err(ContractError::Unauthorized {});
-            }
-        }
-    };
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .unbonding
+    {
+        return Err(ContractError::Paused {
+            operation: "unbonding".into(),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    check_id(deps.storage, &config, &id)?;
+
+    // check if caller is owner of id, or one of its operators
+    check_can_operate(deps.as_ref(), &env, &id, &info.sender, false)?;
+
+    let share_amounts = resolve_share_amounts(&config, share_amount, share_amounts)?;
 
     // track funds as awaiting confirmation for start of unbonding
     // only allow a single unconfirmed unbonding at a time
-    match BOND_STATUS_TRACKER.update(
+    update_bond_status(
         deps.storage,
         id.clone(), // info.sender.clone()),
         |bond_status_data| -> Result<BondStatus, ContractError> {
             let mut bond_status = bond_status_data.unwrap_or_default();
 
-            // prevent unbonding if there are unconfirmed unbonds
-            if bond_status.unconfirmed_unbonding.denom_1 != Uint128::zero()
-                || bond_status.unconfirmed_unbonding.denom_2 != Uint128::zero()
-            {
-                return Err(ContractError::NoStartUnbondingIfExistingUnconfirmed {});
-            }
-
-            // prevent unbonding if share amount is too high
-            // bonded should be same since logic is 1:1, but we'll stay safe
-            if share_amount > bond_status.bonded.denom_1
-                || share_amount > bond_status.bonded.denom_2
+            for share in share_amounts
+                .0
+                .iter()
+                .filter(|share| !share.amount.is_zero())
             {
-                return Err(ContractError::StartUnbondAmountTooHigh {});
-            }
+                // prevent unbonding if there are unconfirmed unbonds
+                if !bond_status
+                    .unconfirmed_unbonding
+                    .get(&share.denom)
+                    .is_zero()
+                {
+                    return Err(ContractError::NoStartUnbondingIfExistingUnconfirmed {});
+                }
 
-            bond_status.bonded.denom_1 -= share_amount;
-            bond_status.bonded.denom_2 -= share_amount;
+                // prevent unbonding if share amount is too high
+                if share.amount > bond_status.bonded.get(&share.denom) {
+                    return Err(ContractError::StartUnbondAmountTooHigh {});
+                }
 
-            bond_status.unconfirmed_unbonding.denom_1 += share_amount;
-            bond_status.unconfirmed_unbonding.denom_2 += share_amount;
+                bond_status.bonded.sub(&share.denom, share.amount)?;
+                bond_status
+                    .unconfirmed_unbonding
+                    .add(&share.denom, share.amount)?;
+            }
 
             return Ok(bond_status);
         },
-    ) {
-        Err(e) => return Err(e),
-        Ok(_) => (),
-    };
-
-    // send messages to the relayers
-    let config = CONFIG.load(deps.storage)?;
+    )?;
 
-    let msg_router_1 = WasmMsg::Execute {
-        contract_addr: config.router_denom_1.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnStartUnbond {
-            id: id.clone(),
-            share_amount: share_amount,
-        })?,
-        funds: vec![],
-    };
+    // send messages to the relayers, routers with nothing to unbond are left out
+    let mut msgs: Vec<SubMsg> = vec![];
+    for asset in &config.assets {
+        let share_amount = share_amounts.get(&asset.denom);
+        if share_amount.is_zero() {
+            continue;
+        }
 
-    let msg_router_2 = WasmMsg::Execute {
-        contract_addr: config.router_denom_2.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnStartUnbond {
-            id: id.clone(),
-            share_amount: share_amount,
-        })?,
-        funds: vec![],
-    };
+        msgs.push(dispatch_to_router(
+            deps.storage,
+            &env,
+            &id,
+            asset,
+            OperationKind::StartUnbond,
+            share_amount,
+            None,
+            |nonce| {
+                return Ok(WasmMsg::Execute {
+                    contract_addr: asset.router.to_string(),
+                    msg: to_binary(&ExternalExecuteMsg::OnStartUnbond {
+                        id: id.clone(),
+                        share_amount,
+                        nonce,
+                    })?,
+                    funds: vec![],
+                });
+            },
+        )?);
+    }
 
-    return Ok(Response::new()
-        .add_message(msg_router_1)
-        .add_message(msg_router_2)
-        .add_event(
-            Event::new("start_unbond")
-                .add_attribute("method", "start_unbond")
-                .add_attribute("caller", info.sender)
-                .add_attribute("id", id)
-                .add_attribute("share_amount", share_amount),
-        ));
+    return Ok(Response::new().add_submessages(msgs).add_event(
+        Event::new("start_unbond")
+            .add_attribute("method", "start_unbond")
+            .add_attribute("caller", info.sender)
+            .add_attribute("id", id)
+            .add_attribute("share_amounts", coins_to_string(&share_amounts.0)),
+    ));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn unbond(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     id: String,
-    share_amount: Uint128,
+    share_amount: Option<Uint128>,
+    share_amounts: Option<Vec<Coin>>,
+    recipient: Option<String>,
+    msg: Option<Binary>,
 ) -> Result<Response, ContractError> {
-    // check if caller is owner of id
-    match ID_TO_ADDRESS_TRACKER.load(deps.storage, id.clone()) {
-        Err(_) => return Err(ContractError::IdNotAllocated {}),
-        Ok(owner) => {
-            if owner != info.sender {
-                return Err(ContractError::Unauthorized {});
-            }
-        }
-    };
+    if PAUSE_STATE
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        .unbonding
+    {
+        return Err(ContractError::Paused {
+            operation: "unbonding".into(),
+        });
+    }
 
-    // load config
     let config = CONFIG.load(deps.storage)?;
+    check_id(deps.storage, &config, &id)?;
+
+    // check if caller is owner of id, or one of its operators. Operators need to be allowed to
+    // send the funds elsewhere than to the owner
+    let custom_recipient = recipient.is_some() || msg.is_some();
+    check_can_operate(deps.as_ref(), &env, &id, &info.sender, custom_recipient)?;
+    let recipient = match recipient {
+        None => None,
+        Some(recipient) => Some(deps.api.addr_validate(&recipient)?),
+    };
+
+    let share_amounts = resolve_share_amounts(&config, share_amount, share_amounts)?;
 
     // now consume in unbonding and set in unconfirmed_unbonded
-    match BOND_STATUS_TRACKER.update(
+    update_bond_status(
         deps.storage,
         id.clone(),
         |bond_status_data| -> Result<BondStatus, ContractError> {
             let mut bond_status = bond_status_data.unwrap_or_default();
 
             // set as sent for unbond, keeping track of intermediary state if there is a problem with the routers
-            bond_status.sent_for_unbond.denom_1 += share_amount;
-            bond_status.sent_for_unbond.denom_2 += share_amount;
+            for share in &share_amounts.0 {
+                bond_status
+                    .sent_for_unbond
+                    .add(&share.denom, share.amount)?;
+            }
 
             // now consume unbonding elements
             // we'll iterate and pop
-            let mut to_consume = (share_amount, share_amount);
+            let mut to_consume = share_amounts.clone();
             let mut kept_elements: Vec<UnbondingElement> = vec![];
-            while to_consume.0 > Uint128::zero() || to_consume.1 > Uint128::zero() {
+            while !to_consume.is_zero() {
                 let mut elem = match bond_status.unbonding.pop() {
                     Some(val) => val,
                     // if no more elements, this means there is not enough unbonded assets to honor the call
                     None => return Err(ContractError::UnbondAmountTooHigh {}),
                 };
 
-                if elem.denom == config.denom_1
-                    && elem
-                        .unbonding_start_time
-                        .plus_seconds(config.lock_period_denom_1)
-                        < env.block.time
-                {
-                    if elem.value > to_consume.0 {
-                        // elem has more value than what's left to consume
-                        // so partial consume and push back
-                        elem.value = elem.value - to_consume.0;
-                        kept_elements.push(elem);
-                        to_consume.0 = Uint128::zero();
-                    } else {
-                        // consume entirely, discard the element
-                        to_consume.0 -= elem.value;
+                let is_unlocked = match config.get_asset(&elem.denom) {
+                    Some(asset) => {
+                        elem.unbonding_start_time.plus_seconds(asset.lock_period) < env.block.time
                     }
-                } else if elem.denom == config.denom_2
-                    && elem
-                        .unbonding_start_time
-                        .plus_seconds(config.lock_period_denom_2)
-                        < env.block.time
-                {
-                    if elem.value > to_consume.1 {
+                    None => false,
+                };
+
+                if is_unlocked {
+                    let remaining = to_consume.get(&elem.denom);
+                    if elem.value > remaining {
                         // elem has more value than what's left to consume
                         // so partial consume and push back
-                        elem.value = elem.value - to_consume.1;
+                        elem.value -= remaining;
+                        to_consume.set(&elem.denom, Uint128::zero());
                         kept_elements.push(elem);
-                        to_consume.1 = Uint128::zero();
                     } else {
                         // consume entirely, discard the element
-                        to_consume.1 -= elem.value;
+                        to_consume.sub(&elem.denom, elem.value)?;
                     }
                 } else {
                     kept_elements.push(elem);
@@ -274,38 +781,356 @@ fn unbond(
             bond_status.unbonding.append(&mut kept_elements);
             return Ok(bond_status);
         },
-    ) {
-        Err(e) => return Err(e),
-        Ok(_) => (),
-    };
+    )?;
 
     // now send messages to router to get the assets back
-    let msg_router_1 = WasmMsg::Execute {
-        contract_addr: config.router_denom_1.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnUnbond {
-            id: id.clone(),
-            share_amount: share_amount,
-        })?,
-        funds: vec![],
+    let mut msgs: Vec<SubMsg> = vec![];
+    for asset in &config.assets {
+        let share_amount = share_amounts.get(&asset.denom);
+        if share_amount.is_zero() {
+            continue;
+        }
+
+        msgs.push(dispatch_to_router(
+            deps.storage,
+            &env,
+            &id,
+            asset,
+            OperationKind::Unbond,
+            share_amount,
+            Some(Withdrawal {
+                recipient: recipient.clone(),
+                msg: msg.clone(),
+            }),
+            |nonce| {
+                return Ok(WasmMsg::Execute {
+                    contract_addr: asset.router.to_string(),
+                    msg: to_binary(&ExternalExecuteMsg::OnUnbond {
+                        id: id.clone(),
+                        share_amount,
+                        nonce,
+                    })?,
+                    funds: vec![],
+                });
+            },
+        )?);
+    }
+
+    return Ok(Response::new().add_submessages(msgs).add_event(
+        Event::new("unbond")
+            .add_attribute("method", "unbond")
+            .add_attribute("caller", info.sender)
+            .add_attribute("id", id)
+            .add_attribute("share_amounts", coins_to_string(&share_amounts.0))
+            .add_attribute(
+                "recipient",
+                recipient
+                    .map(|recipient| recipient.into_string())
+                    .unwrap_or_default(),
+            ),
+    ));
+}
+
+fn reclaim_stale_bond(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    // check if caller is owner of id, or one of its operators. The funds go to the owner
+    let owner = check_can_operate(deps.as_ref(), &env, &id, &info.sender, false)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let stale_operations = pending_operations()
+        .sub_prefix(id.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, operation)| operation))
+        .collect::<StdResult<Vec<PendingOperation>>>()?
+        .into_iter()
+        .filter(|operation| {
+            operation.kind == OperationKind::Bond
+                && !operation.reclaimed
+                && operation.dispatched_at.plus_seconds(config.bond_timeout) <= env.block.time
+        })
+        .collect::<Vec<PendingOperation>>();
+    if stale_operations.is_empty() {
+        return Err(ContractError::NoStaleBond {});
+    }
+
+    // ask the routers for the funds, the bonds stay in sent_to_bond until they are returned
+    let mut msgs: Vec<WasmMsg> = vec![];
+    let mut reclaimed: Vec<Coin> = vec![];
+    for mut operation in stale_operations {
+        msgs.push(WasmMsg::Execute {
+            contract_addr: operation.router.to_string(),
+            msg: to_binary(&ExternalExecuteMsg::ReturnBond {
+                id: id.clone(),
+                amount: operation.amount,
+                nonce: operation.nonce,
+            })?,
+            funds: vec![],
+        });
+        reclaimed.push(coin(operation.amount.u128(), &operation.denom));
+
+        operation.reclaimed = true;
+        pending_operations().save(deps.storage, operation.key(), &operation)?;
+    }
+
+    return Ok(Response::new().add_messages(msgs).add_event(
+        Event::new("reclaim_stale_bond")
+            .add_attribute("method", "reclaim_stale_bond")
+            .add_attribute("caller", info.sender)
+            .add_attribute("owner", owner)
+            .add_attribute("id", id)
+            .add_attribute("reclaimed", coins_to_string(&reclaimed)),
+    ));
+}
+
+fn transfer_id(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    new_owner: String,
+) -> Result<Response, ContractError> {
+    // check if caller is owner of id
+    match id_to_address_tracker().load(deps.storage, id.clone()) {
+        Err(_) => return Err(ContractError::IdNotAllocated {}),
+        Ok(owner) => {
+            if owner != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
     };
 
-    let msg_router_2 = WasmMsg::Execute {
-        contract_addr: config.router_denom_2.into_string(),
-        msg: to_binary(&ExternalExecuteMsg::OnUnbond {
-            id: id.clone(),
-            share_amount: share_amount,
-        })?,
-        funds: vec![],
+    // the bond status is keyed by id, so only the owner changes. Funds of operations still in
+    // flight are sent to the new owner once the routers answer
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    id_to_address_tracker().save(deps.storage, id.clone(), &new_owner)?;
+
+    // approvals were given by the previous owner
+    let operators = APPROVALS
+        .prefix(id.clone())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<Addr>>>()?;
+    for operator in operators {
+        APPROVALS.remove(deps.storage, (id.clone(), operator));
+    }
+
+    return Ok(Response::new().add_event(
+        Event::new("transfer_id")
+            .add_attribute("method", "transfer_id")
+            .add_attribute("id", id)
+            .add_attribute("previous_owner", info.sender)
+            .add_attribute("new_owner", new_owner),
+    ));
+}
+
+fn close_id(deps: DepsMut, info: MessageInfo, id: String) -> Result<Response, ContractError> {
+    // check if caller is owner of id
+    match id_to_address_tracker().load(deps.storage, id.clone()) {
+        Err(_) => return Err(ContractError::IdNotAllocated {}),
+        Ok(owner) => {
+            if owner != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
     };
 
-    return Ok(Response::new()
-        .add_message(msg_router_1)
-        .add_message(msg_router_2)
-        .add_event(
-            Event::new("unbond")
-                .add_attribute("method", "unbond")
-                .add_attribute("caller", info.sender)
-                .add_attribute("id", id)
-                .add_attribute("share_amount", share_amount),
-        ));
+    let bond_status = BOND_STATUS_TRACKER
+        .may_load(deps.storage, id.clone())?
+        .unwrap_or_default();
+    return match close_position_if_empty(deps.storage, &id, &bond_status)? {
+        None => Err(ContractError::PositionNotEmpty {}),
+        Some(event) => Ok(Response::new().add_event(event)),
+    };
+}
+
+/// Remove the position and everything attached to the id once nothing is left in it, so the id
+/// can be reused. Returns the position_closed event if the position was closed
+pub(crate) fn close_position_if_empty(
+    storage: &mut dyn Storage,
+    id: &str,
+    bond_status: &BondStatus,
+) -> StdResult<Option<Event>> {
+    if !bond_status.is_empty() {
+        return Ok(None);
+    }
+
+    let owner = id_to_address_tracker().may_load(storage, id.to_owned())?;
+    BOND_STATUS_TRACKER.remove(storage, id.to_owned());
+    id_to_address_tracker().remove(storage, id.to_owned())?;
+    let operators = APPROVALS
+        .prefix(id.to_owned())
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<Addr>>>()?;
+    for operator in operators {
+        APPROVALS.remove(storage, (id.to_owned(), operator));
+    }
+
+    return Ok(Some(
+        Event::new("position_closed")
+            .add_attribute("method", "close_position")
+            .add_attribute("id", id)
+            .add_attribute(
+                "owner",
+                owner.map(|owner| owner.into_string()).unwrap_or_default(),
+            ),
+    ));
+}
+
+fn approve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: String,
+    operator: String,
+    expires: Option<Expiration>,
+    allow_custom_recipient: Option<bool>,
+) -> Result<Response, ContractError> {
+    // check if caller is owner of id
+    match id_to_address_tracker().load(deps.storage, id.clone()) {
+        Err(_) => return Err(ContractError::IdNotAllocated {}),
+        Ok(owner) => {
+            if owner != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
+    };
+
+    let operator = deps.api.addr_validate(&operator)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::ApprovalExpired {});
+    }
+    let approval = Approval {
+        operator: operator.clone(),
+        expires,
+        allow_custom_recipient: allow_custom_recipient.unwrap_or_default(),
+    };
+    APPROVALS.save(deps.storage, (id.clone(), operator.clone()), &approval)?;
+
+    return Ok(Response::new().add_event(
+        Event::new("approve")
+            .add_attribute("method", "approve")
+            .add_attribute("owner", info.sender)
+            .add_attribute("id", id)
+            .add_attribute("operator", operator)
+            .add_attribute("expires", expires.to_string())
+            .add_attribute(
+                "allow_custom_recipient",
+                approval.allow_custom_recipient.to_string(),
+            ),
+    ));
+}
+
+fn revoke(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: String,
+    operator: String,
+) -> Result<Response, ContractError> {
+    // check if caller is owner of id
+    match id_to_address_tracker().load(deps.storage, id.clone()) {
+        Err(_) => return Err(ContractError::IdNotAllocated {}),
+        Ok(owner) => {
+            if owner != info.sender {
+                return Err(ContractError::Unauthorized {});
+            }
+        }
+    };
+
+    let operator = deps.api.addr_validate(&operator)?;
+    APPROVALS.remove(deps.storage, (id.clone(), operator.clone()));
+
+    return Ok(Response::new().add_event(
+        Event::new("revoke")
+            .add_attribute("method", "revoke")
+            .add_attribute("owner", info.sender)
+            .add_attribute("id", id)
+            .add_attribute("operator", operator),
+    ));
+}
+
+fn approve_all(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    operator: String,
+    expires: Option<Expiration>,
+    allow_custom_recipient: Option<bool>,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::ApprovalExpired {});
+    }
+    let approval = Approval {
+        operator: operator.clone(),
+        expires,
+        allow_custom_recipient: allow_custom_recipient.unwrap_or_default(),
+    };
+    OPERATORS.save(
+        deps.storage,
+        (info.sender.clone(), operator.clone()),
+        &approval,
+    )?;
+
+    return Ok(Response::new().add_event(
+        Event::new("approve_all")
+            .add_attribute("method", "approve_all")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator)
+            .add_attribute("expires", expires.to_string())
+            .add_attribute(
+                "allow_custom_recipient",
+                approval.allow_custom_recipient.to_string(),
+            ),
+    ));
+}
+
+fn revoke_all(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    OPERATORS.remove(deps.storage, (info.sender.clone(), operator.clone()));
+
+    return Ok(Response::new().add_event(
+        Event::new("revoke_all")
+            .add_attribute("method", "revoke_all")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator),
+    ));
+}
+
+/// Load the owner of the id, checking the caller is the owner or an operator it approved.
+/// With `custom_recipient`, the operator must also be allowed to send funds elsewhere
+fn check_can_operate(
+    deps: Deps,
+    env: &Env,
+    id: &str,
+    caller: &Addr,
+    custom_recipient: bool,
+) -> Result<Addr, ContractError> {
+    let owner = match id_to_address_tracker().may_load(deps.storage, id.to_owned())? {
+        None => return Err(ContractError::IdNotAllocated {}),
+        Some(owner) => owner,
+    };
+    if &owner == caller {
+        return Ok(owner);
+    }
+
+    let approvals = [
+        APPROVALS.may_load(deps.storage, (id.to_owned(), caller.clone()))?,
+        OPERATORS.may_load(deps.storage, (owner.clone(), caller.clone()))?,
+    ];
+    if approvals.iter().flatten().any(|approval| {
+        !approval.expires.is_expired(&env.block)
+            && (!custom_recipient || approval.allow_custom_recipient)
+    }) {
+        return Ok(owner);
+    }
+
+    return Err(ContractError::Unauthorized {});
 }
@@ -0,0 +1,191 @@
+use cosmwasm_std::{coin, Addr, Decimal, DepsMut, Env, Order, Response, StdResult, Storage};
+use cw2::{get_contract_version, set_contract_version};
+use semver::Version;
+
+use crate::{
+    contract::{CONTRACT_NAME, CONTRACT_VERSION},
+    contract_execute::next_nonce,
+    legacy::v0_1,
+    msg::MigrateMsg,
+    state::{id_to_address_tracker, pending_operations, BOND_STATUS_TRACKER, CONFIG, TOTALS},
+    typing::{
+        AssetConfig, BondStatus, BondStatusData, Config, IdValidation, OperationKind,
+        PendingOperation, RatioSource, Totals, UnbondingElement, DEFAULT_BOND_TIMEOUT,
+    },
+    ContractError,
+};
+
+pub fn route_migrate(
+    mut deps: DepsMut,
+    env: Env,
+    msg: MigrateMsg,
+) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::InvalidMigrationContract {
+            contract: stored.contract,
+        });
+    }
+
+    // refuse downgrades, storage written by a newer version may not be readable anymore
+    let stored_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > new_version {
+        return Err(ContractError::CannotMigrateToOlderVersion {
+            stored_version: stored.version,
+            new_version: CONTRACT_VERSION.to_owned(),
+        });
+    }
+
+    // apply the storage migrations in order
+    if stored_version < Version::new(0, 2, 0) {
+        migrate_from_v0_1(deps.branch(), &env, &msg, &stored.version)?;
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    return Ok(Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("previous_version", stored.version)
+        .add_attribute("version", CONTRACT_VERSION));
+}
+
+/// Migrate storage written by v0.1.x: the config gains an admin, bond statuses are
+/// rewritten in the current layout, the totals and owner index are built, and operations
+/// still waiting on the routers are recorded
+fn migrate_from_v0_1(
+    deps: DepsMut,
+    env: &Env,
+    msg: &MigrateMsg,
+    stored_version: &str,
+) -> Result<(), ContractError> {
+    let admin = match &msg.admin {
+        None => {
+            return Err(ContractError::MigrationAdminRequired {
+                stored_version: stored_version.to_owned(),
+            })
+        }
+        Some(admin) => deps.api.addr_validate(admin)?,
+    };
+
+    let old_config = v0_1::CONFIG.load(deps.storage)?;
+    let config = Config {
+        admin,
+        guardian: None,
+        assets: vec![
+            AssetConfig {
+                denom: old_config.denom_1,
+                router: old_config.router_denom_1,
+                lock_period: old_config.lock_period_denom_1,
+                ratio: Decimal::one(),
+            },
+            AssetConfig {
+                denom: old_config.denom_2,
+                router: old_config.router_denom_2,
+                lock_period: old_config.lock_period_denom_2,
+                ratio: Decimal::one(),
+            },
+        ],
+        ratio_source: RatioSource::Static {},
+        ratio_tolerance: Decimal::zero(),
+        refund_excess: false,
+        swap_contract: None,
+        id_validation: IdValidation::default(),
+        bond_timeout: DEFAULT_BOND_TIMEOUT,
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    let old_bond_statuses = v0_1::BOND_STATUS_TRACKER
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<(String, v0_1::BondStatus)>>>()?;
+
+    // amounts were stored in fixed fields, they are now keyed by denom
+    let denom_1 = &config.assets[0].denom;
+    let denom_2 = &config.assets[1].denom;
+    let mut totals = Totals::default();
+    for (id, old_bond_status) in old_bond_statuses {
+        let bond_status = migrate_bond_status(old_bond_status, denom_1, denom_2);
+        totals.add_bond_status(&bond_status)?;
+        record_pending_operations(deps.storage, env, &config, &id, &bond_status)?;
+        BOND_STATUS_TRACKER.save(deps.storage, id, &bond_status)?;
+    }
+    TOTALS.save(deps.storage, &totals)?;
+
+    // owners are stored as before, saving them again fills the owner index
+    let owners = v0_1::ID_TO_ADDRESS_TRACKER
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<(String, Addr)>>>()?;
+    for (id, owner) in owners {
+        id_to_address_tracker().save(deps.storage, id, &owner)?;
+    }
+
+    return Ok(());
+}
+
+/// Record the operations v0.1 sent to the routers and is still waiting on, one per stage and denom,
+/// so their callbacks can be settled. The time they were sent is unknown, the migration is used
+fn record_pending_operations(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    id: &str,
+    bond_status: &BondStatus,
+) -> StdResult<()> {
+    for asset in &config.assets {
+        let stages = [
+            (OperationKind::Bond, &bond_status.sent_to_bond),
+            (
+                OperationKind::StartUnbond,
+                &bond_status.unconfirmed_unbonding,
+            ),
+            (OperationKind::Unbond, &bond_status.sent_for_unbond),
+        ];
+        for (kind, data) in stages {
+            let amount = data.get(&asset.denom);
+            if amount.is_zero() {
+                continue;
+            }
+
+            let operation = PendingOperation {
+                nonce: next_nonce(storage)?,
+                id: id.to_owned(),
+                router: asset.router.clone(),
+                denom: asset.denom.to_owned(),
+                kind,
+                amount,
+                dispatched_at: env.block.time,
+                accepted: true,
+                reclaimed: false,
+                withdrawal: None,
+            };
+            pending_operations().save(storage, operation.key(), &operation)?;
+        }
+    }
+
+    return Ok(());
+}
+
+fn migrate_bond_status(old: v0_1::BondStatus, denom_1: &str, denom_2: &str) -> BondStatus {
+    let migrate_data = |data: v0_1::BondStatusData| {
+        BondStatusData(vec![
+            coin(data.denom_1.u128(), denom_1),
+            coin(data.denom_2.u128(), denom_2),
+        ])
+    };
+
+    return BondStatus {
+        sent_to_bond: migrate_data(old.sent_to_bond),
+        bonded: migrate_data(old.bonded),
+        unconfirmed_unbonding: migrate_data(old.unconfirmed_unbonding),
+        unbonding: old
+            .unbonding
+            .into_iter()
+            .map(|elem| UnbondingElement {
+                denom: elem.denom,
+                value: elem.value,
+                unbonding_start_time: elem.unbonding_start_time,
+            })
+            .collect(),
+        sent_for_unbond: migrate_data(old.sent_for_unbond),
+    };
+}
@@ -1,19 +1,57 @@
-use cosmwasm_std::{to_binary, Binary, Deps, Env, StdResult, Uint128};
+use cosmwasm_std::{
+    to_binary, Binary, Coin, Decimal, Decimal256, Deps, Env, Order, StdResult, Uint128, Uint256,
+};
+use cw_storage_plus::{Bound, PrefixBound};
 use erased_serde::Serialize;
 
 use crate::{
-    msg::{GetBondStatusResponse, GetConfigResponse, GetSharesAvailableUnbondResponse, QueryMsg},
-    state::{BOND_STATUS_TRACKER, CONFIG},
+    msg::{
+        BondStatusEntry, ConvertToAssetsResponse, ExternalQueryMsg, GetApprovalsResponse,
+        GetBondStatusResponse, GetConfigResponse, GetExchangeRatesResponse, GetIdsByOwnerResponse,
+        GetOperatorsResponse, GetPauseStateResponse, GetPendingAdminResponse,
+        GetPositionValueResponse, GetSharesAvailableUnbondResponse, GetTotalsResponse,
+        ListBondStatusesResponse, ListPendingOperationsResponse, PositionValue, QueryMsg,
+    },
+    state::{
+        id_to_address_tracker, pending_operations, APPROVALS, BOND_STATUS_TRACKER, CONFIG,
+        EXCHANGE_RATES, OPERATORS, PAUSE_STATE, PENDING_ADMIN, TOTALS,
+    },
+    typing::PendingOperation,
 };
 
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
 pub fn route_query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     let data = match msg {
         QueryMsg::GetConfig {} => get_config(deps),
+        QueryMsg::GetPendingAdmin {} => get_pending_admin(deps),
+        QueryMsg::GetPauseState {} => get_pause_state(deps),
         QueryMsg::GetBondStatus { id } => get_bond_status(deps, id),
+        QueryMsg::GetTotals {} => get_totals(deps),
+        QueryMsg::ListBondStatuses { start_after, limit } => {
+            list_bond_statuses(deps, start_after, limit)
+        }
+        QueryMsg::GetIdsByOwner {
+            owner,
+            start_after,
+            limit,
+        } => get_ids_by_owner(deps, owner, start_after, limit)?,
+        QueryMsg::GetApprovals { id } => get_approvals(deps, env, id),
+        QueryMsg::GetOperators { owner } => get_operators(deps, env, owner)?,
         QueryMsg::GetSharesAvailableUnbond { id } => get_shares_available_unbond(deps, env, id),
+        QueryMsg::GetPositionValue { id } => get_position_value(deps, id)?,
+        QueryMsg::GetExchangeRates {} => get_exchange_rates(deps),
+        QueryMsg::ListPendingOperations {
+            id,
+            router,
+            older_than,
+            start_after,
+            limit,
+        } => list_pending_operations(deps, env, id, router, older_than, start_after, limit)?,
     };
 
-    return Ok(to_binary(&data)?);
+    return to_binary(&data);
 }
 
 fn get_config(deps: Deps) -> Box<dyn Serialize> {
@@ -22,17 +60,125 @@ fn get_config(deps: Deps) -> Box<dyn Serialize> {
     });
 }
 
+fn get_pending_admin(deps: Deps) -> Box<dyn Serialize> {
+    return Box::new(GetPendingAdminResponse {
+        pending_admin: PENDING_ADMIN.may_load(deps.storage).unwrap(),
+    });
+}
+
+fn get_pause_state(deps: Deps) -> Box<dyn Serialize> {
+    return Box::new(GetPauseStateResponse {
+        pause_state: PAUSE_STATE
+            .may_load(deps.storage)
+            .unwrap()
+            .unwrap_or_default(),
+    });
+}
+
 fn get_bond_status(deps: Deps, id: String) -> Box<dyn Serialize> {
     return Box::new(GetBondStatusResponse {
         bond_status: BOND_STATUS_TRACKER.may_load(deps.storage, id).unwrap(),
     });
 }
 
+fn get_totals(deps: Deps) -> Box<dyn Serialize> {
+    return Box::new(GetTotalsResponse {
+        totals: TOTALS.may_load(deps.storage).unwrap().unwrap_or_default(),
+    });
+}
+
+fn list_bond_statuses(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> Box<dyn Serialize> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let bond_statuses = BOND_STATUS_TRACKER
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (id, bond_status) = item.unwrap();
+            BondStatusEntry {
+                owner: id_to_address_tracker()
+                    .load(deps.storage, id.clone())
+                    .unwrap(),
+                id,
+                bond_status,
+            }
+        })
+        .collect();
+
+    return Box::new(ListBondStatusesResponse { bond_statuses });
+}
+
+fn get_ids_by_owner(
+    deps: Deps,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Box<dyn Serialize>> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let bond_statuses = id_to_address_tracker()
+        .idx
+        .owner
+        .prefix(owner)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (id, owner) = item.unwrap();
+            BondStatusEntry {
+                bond_status: BOND_STATUS_TRACKER.load(deps.storage, id.clone()).unwrap(),
+                id,
+                owner,
+            }
+        })
+        .collect();
+
+    return Ok(Box::new(GetIdsByOwnerResponse { bond_statuses }));
+}
+
+fn get_approvals(deps: Deps, env: Env, id: String) -> Box<dyn Serialize> {
+    let approvals = APPROVALS
+        .prefix(id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.unwrap().1)
+        .filter(|approval| !approval.expires.is_expired(&env.block))
+        .collect();
+
+    return Box::new(GetApprovalsResponse { approvals });
+}
+
+fn get_operators(deps: Deps, env: Env, owner: String) -> StdResult<Box<dyn Serialize>> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let operators = OPERATORS
+        .prefix(owner)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.unwrap().1)
+        .filter(|approval| !approval.expires.is_expired(&env.block))
+        .collect();
+
+    return Ok(Box::new(GetOperatorsResponse { operators }));
+}
+
 fn get_shares_available_unbond(deps: Deps, env: Env, id: String) -> Box<dyn Serialize> {
     let bond_status = match BOND_STATUS_TRACKER.load(deps.storage, id) {
         Err(_) => {
             return Box::new(GetSharesAvailableUnbondResponse {
                 shares_available_unbond: Uint128::zero(),
+                shares_available_unbond_per_denom: vec![],
             })
         }
         Ok(data) => data,
@@ -40,33 +186,186 @@ fn get_shares_available_unbond(deps: Deps, env: Env, id: String) -> Box<dyn Seri
 
     let config = CONFIG.load(deps.storage).unwrap();
 
-    let available_denom_1: Uint128 = bond_status
-        .unbonding
+    let shares_available_unbond_per_denom: Vec<Coin> = config
+        .assets
         .iter()
-        .filter(|elem| {
-            elem.denom == config.denom_1
-                && elem
-                    .unbonding_start_time
-                    .plus_seconds(config.lock_period_denom_1)
-                    < env.block.time
+        .map(|asset| Coin {
+            denom: asset.denom.to_owned(),
+            amount: bond_status
+                .unbonding
+                .iter()
+                .filter(|elem| {
+                    elem.denom == asset.denom
+                        && elem.unbonding_start_time.plus_seconds(asset.lock_period)
+                            < env.block.time
+                })
+                .map(|elem| elem.value)
+                .sum::<Uint128>(),
         })
-        .map(|elem| elem.value)
-        .sum();
+        .collect();
 
-    let available_denom_2: Uint128 = bond_status
-        .unbonding
+    // a single share_amount applies to every denom, so only the smallest available amount can be used
+    let shares_available_unbond = shares_available_unbond_per_denom
         .iter()
-        .filter(|elem| {
-            elem.denom == config.denom_2
-                && elem
-                    .unbonding_start_time
-                    .plus_seconds(config.lock_period_denom_2)
-                    < env.block.time
-        })
-        .map(|elem| elem.value)
-        .sum();
+        .map(|coin| coin.amount)
+        .min()
+        .unwrap_or_default();
 
     return Box::new(GetSharesAvailableUnbondResponse {
-        shares_available_unbond: std::cmp::min(available_denom_1, available_denom_2),
+        shares_available_unbond,
+        shares_available_unbond_per_denom,
     });
 }
+
+fn get_position_value(deps: Deps, id: String) -> StdResult<Box<dyn Serialize>> {
+    let bond_status = BOND_STATUS_TRACKER
+        .may_load(deps.storage, id)?
+        .unwrap_or_default();
+    let config = CONFIG.load(deps.storage)?;
+
+    let mut values = vec![];
+    for asset in &config.assets {
+        let denom = &asset.denom;
+        let unbonding = bond_status
+            .unbonding
+            .iter()
+            .filter(|elem| &elem.denom == denom)
+            .map(|elem| elem.value)
+            .sum::<Uint128>();
+        let shares = bond_status.bonded.get(denom)
+            + bond_status.unconfirmed_unbonding.get(denom)
+            + unbonding
+            + bond_status.sent_for_unbond.get(denom);
+
+        // the router knows best what its shares are worth, v0.1 routers cannot convert them
+        // so they are valued at the last rate they bonded at, 1:1 before any bond
+        let shares_value = if shares.is_zero() {
+            Uint128::zero()
+        } else {
+            let response: StdResult<ConvertToAssetsResponse> = deps.querier.query_wasm_smart(
+                asset.router.as_str(),
+                &ExternalQueryMsg::ConvertToAssets { shares },
+            );
+            match response {
+                Ok(response) => response.amount,
+                Err(_) => {
+                    let rate = EXCHANGE_RATES
+                        .may_load(deps.storage, asset.router.clone())?
+                        .map(|exchange_rate| exchange_rate.rate)
+                        .unwrap_or_else(Decimal::one);
+                    let value = Uint256::from(shares) * Decimal256::from(rate);
+                    Uint128::try_from(value)?
+                }
+            }
+        };
+        let sent_to_bond = bond_status.sent_to_bond.get(denom);
+
+        values.push(PositionValue {
+            denom: denom.to_owned(),
+            shares,
+            shares_value,
+            sent_to_bond,
+            value: shares_value + sent_to_bond,
+        });
+    }
+
+    return Ok(Box::new(GetPositionValueResponse { values }));
+}
+
+fn get_exchange_rates(deps: Deps) -> Box<dyn Serialize> {
+    let exchange_rates = EXCHANGE_RATES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.unwrap().1)
+        .collect();
+
+    return Box::new(GetExchangeRatesResponse { exchange_rates });
+}
+
+fn list_pending_operations(
+    deps: Deps,
+    env: Env,
+    id: Option<String>,
+    router: Option<String>,
+    older_than: Option<u64>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Box<dyn Serialize>> {
+    let router = match router {
+        None => None,
+        Some(router) => Some(deps.api.addr_validate(&router)?),
+    };
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    // every branch yields the operations ordered by nonce, starting after start_after
+    let stored = pending_operations();
+    let operations: Box<dyn Iterator<Item = StdResult<PendingOperation>>> = match (id, router) {
+        (Some(id), Some(router)) => Box::new(
+            stored
+                .prefix((id, router))
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .map(|item| item.map(|(_, operation)| operation)),
+        ),
+        (Some(id), None) => {
+            // an id only has a few operations in flight, on a few routers
+            let mut operations = stored
+                .sub_prefix(id)
+                .range(deps.storage, None, None, Order::Ascending)
+                .map(|item| item.map(|(_, operation)| operation))
+                .collect::<StdResult<Vec<PendingOperation>>>()?;
+            if let Some(start_after) = start_after {
+                operations.retain(|operation| operation.nonce > start_after);
+            }
+            operations.sort_by_key(|operation| operation.nonce);
+            Box::new(operations.into_iter().map(Ok))
+        }
+        (None, Some(router)) => {
+            let start = match start_after {
+                None => PrefixBound::inclusive((router.clone(), 0)),
+                Some(nonce) => PrefixBound::exclusive((router.clone(), nonce)),
+            };
+            Box::new(
+                stored
+                    .idx
+                    .router
+                    .prefix_range_raw(
+                        deps.storage,
+                        Some(start),
+                        Some(PrefixBound::inclusive((router, u64::MAX))),
+                        Order::Ascending,
+                    )
+                    .map(|item| item.map(|(_, operation)| operation)),
+            )
+        }
+        (None, None) => Box::new(
+            stored
+                .idx
+                .nonce
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .map(|item| item.map(|(_, operation)| operation)),
+        ),
+    };
+
+    let pending_operations = operations
+        .filter(|item| match (item, older_than) {
+            (Ok(operation), Some(older_than)) => {
+                operation.dispatched_at.plus_seconds(older_than) <= env.block.time
+            }
+            _ => true,
+        })
+        .take(limit)
+        .collect::<StdResult<Vec<PendingOperation>>>()?;
+
+    return Ok(Box::new(ListPendingOperationsResponse {
+        pending_operations,
+    }));
+}
@@ -0,0 +1,170 @@
+use cosmwasm_std::{coin, DepsMut, Env, Event, Reply, Response, Storage, SubMsgResult, Timestamp};
+
+use crate::{
+    contract_execute::{bond_funds, close_position_if_empty},
+    state::{load_pending_operation, pending_operations, update_bond_status, CONFIG, PENDING_SWAP},
+    typing::{BondStatus, OperationKind, PendingOperation, UnbondingElement},
+    ContractError,
+};
+
+/// Reply id of the swap of a single sided deposit
+pub const SWAP_REPLY_ID: u64 = 0;
+
+pub fn route_reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        SWAP_REPLY_ID => swap_reply(deps, env),
+        _ => router_reply(deps, msg),
+    }
+}
+
+/// The swap of a single sided deposit succeeded, bond the kept part with the swapped amount
+fn swap_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending_swap = PENDING_SWAP.load(deps.storage)?;
+    PENDING_SWAP.remove(deps.storage);
+
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &pending_swap.ask_denom)?
+        .amount;
+    let return_amount = balance.checked_sub(pending_swap.balance_before)?;
+    if return_amount < pending_swap.min_out {
+        return Err(ContractError::SlippageExceeded {
+            min_out: pending_swap.min_out,
+            return_amount,
+        });
+    }
+
+    // funds are ordered as in the config, and the swap rarely lands exactly on the ratio,
+    // so the excess is always refunded to the owner
+    let config = CONFIG.load(deps.storage)?;
+    let swapped = coin(return_amount.u128(), &pending_swap.ask_denom);
+    let funds = config
+        .assets
+        .iter()
+        .map(|asset| {
+            if asset.denom == swapped.denom {
+                swapped.clone()
+            } else {
+                pending_swap.kept.clone()
+            }
+        })
+        .collect();
+
+    let response = bond_funds(
+        deps,
+        &env,
+        config,
+        pending_swap.owner,
+        pending_swap.id,
+        funds,
+        true,
+    )?;
+
+    return Ok(response.add_event(
+        Event::new("swap_reply")
+            .add_attribute("method", "swap_reply")
+            .add_attribute("return_amount", return_amount),
+    ));
+}
+
+/// A router executed or rejected an operation. Rejected unbondings are rolled back to their
+/// previous stage, so their amounts do not stay stuck waiting for a callback that never comes.
+/// A rejected bond fails the whole deposit, since the other routers of the deposit may already
+/// hold their side and only reverting the transaction keeps the position balanced
+fn router_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let reply_id = msg.id;
+    let error = match msg.result {
+        SubMsgResult::Ok(_) => {
+            // the router may already have confirmed the operation with a callback
+            let mut event = Event::new("router_reply")
+                .add_attribute("method", "router_reply")
+                .add_attribute("reply_id", reply_id.to_string())
+                .add_attribute("accepted", "true");
+            if let Some(mut operation) = load_pending_operation(deps.storage, reply_id)? {
+                event = event
+                    .add_attribute("id", &operation.id)
+                    .add_attribute("router", &operation.router);
+                operation.accepted = true;
+                pending_operations().save(deps.storage, operation.key(), &operation)?;
+            }
+            return Ok(Response::new().add_event(event));
+        }
+        SubMsgResult::Err(error) => error,
+    };
+
+    let operation = match load_pending_operation(deps.storage, reply_id)? {
+        None => return Err(ContractError::UnknownOperation { nonce: reply_id }),
+        Some(operation) => operation,
+    };
+    if operation.kind == OperationKind::Bond {
+        return Err(ContractError::BondRejected {
+            denom: operation.denom,
+            error,
+        });
+    }
+    pending_operations().remove(deps.storage, operation.key())?;
+
+    let bond_status = rollback_operation(deps.storage, &operation)?;
+
+    let mut response = Response::new().add_event(
+        Event::new("router_reply")
+            .add_attribute("method", "router_reply")
+            .add_attribute("reply_id", reply_id.to_string())
+            .add_attribute("accepted", "false")
+            .add_attribute("id", &operation.id)
+            .add_attribute("router", &operation.router)
+            .add_attribute("kind", format!("{:?}", operation.kind))
+            .add_attribute(
+                "amount",
+                coin(operation.amount.u128(), &operation.denom).to_string(),
+            )
+            .add_attribute("error", error),
+    );
+
+    // the last unbonding of a position may leave nothing behind
+    if let Some(event) = close_position_if_empty(deps.storage, &operation.id, &bond_status)? {
+        response = response.add_event(event);
+    }
+
+    return Ok(response);
+}
+
+/// Move the amount of an operation that failed back to its previous stage
+pub(crate) fn rollback_operation(
+    storage: &mut dyn Storage,
+    operation: &PendingOperation,
+) -> Result<BondStatus, ContractError> {
+    let denom = &operation.denom;
+    let bond_status = update_bond_status(
+        storage,
+        operation.id.clone(),
+        |bond_status| -> Result<BondStatus, ContractError> {
+            let mut bond_status = bond_status.unwrap_or_default();
+
+            match operation.kind {
+                OperationKind::Bond => {
+                    bond_status.sent_to_bond.sub(denom, operation.amount)?;
+                }
+                OperationKind::StartUnbond => {
+                    bond_status
+                        .unconfirmed_unbonding
+                        .sub(denom, operation.amount)?;
+                    bond_status.bonded.add(denom, operation.amount)?;
+                }
+                OperationKind::Unbond => {
+                    // the consumed elements were unlocked, they come back as unlocked
+                    bond_status.sent_for_unbond.sub(denom, operation.amount)?;
+                    bond_status.unbonding.push(UnbondingElement {
+                        denom: denom.to_owned(),
+                        value: operation.amount,
+                        unbonding_start_time: Timestamp::from_seconds(0),
+                    });
+                }
+            }
+
+            return Ok(bond_status);
+        },
+    )?;
+
+    return Ok(bond_status);
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{CheckedFromRatioError, Coin, OverflowError, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,6 +6,15 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    CheckedFromRatio(#[from] CheckedFromRatioError),
+
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
     #[error("Unauthorized")]
     Unauthorized {},
     // Add any other custom errors you like here.
@@ -17,15 +26,27 @@ pub enum ContractError {
     #[error("Need {req_amount_denoms} assets deposited")]
     MismatchAmountDenoms { req_amount_denoms: u32 },
 
-    #[error("Funds amounts must be equal")]
+    #[error("Funds amounts must match the configured ratios")]
     FundsAmountNotEqual {},
 
-    #[error("The two denoms in funds must be different")]
+    #[error("Denoms in funds must be different")]
     FundsDenomAreSame {},
 
     #[error("Invalid denom: {denom}")]
     InvalidDenom { denom: String },
 
+    #[error("Price of {denom} must be positive")]
+    InvalidPrice { denom: String },
+
+    #[error("Exactly one of share_amount and share_amounts must be set")]
+    InvalidShareAmounts {},
+
+    #[error("Swap returns {return_amount}, below the minimum of {min_out}")]
+    SlippageExceeded {
+        min_out: Uint128,
+        return_amount: Uint128,
+    },
+
     #[error("Requested amount in start unbond higher than amount bonded assets")]
     StartUnbondAmountTooHigh {},
 
@@ -42,7 +63,101 @@ pub enum ContractError {
     #[error("No previous bonding data")]
     NoPreviousBondData {},
 
+    #[error("Position still holds funds")]
+    PositionNotEmpty {},
+
+    #[error("Approval has expired")]
+    ApprovalExpired {},
+
+    #[error("Invalid id: {reason}")]
+    InvalidId { reason: String },
+
+    // admin errors
+    #[error("At least one asset must be configured")]
+    NoAssets {},
+
+    #[error("Minimum id length cannot be above the maximum")]
+    InvalidIdValidation {},
+
+    #[error("Ratio of {denom} must be positive")]
+    InvalidRatio { denom: String },
+
+    #[error("Denom {denom} is configured more than once")]
+    DuplicateDenom { denom: String },
+
+    #[error("Routers must be different")]
+    RoutersAreSame {},
+
+    #[error("Cannot replace router for {denom} while it holds shares or has pending operations")]
+    RouterHasPendingOperations { denom: String },
+
+    #[error("No admin transfer in progress")]
+    NoPendingAdminTransfer {},
+
+    #[error("Admin transfer proposal has expired")]
+    AdminTransferExpired {},
+
+    #[error("{operation} is paused")]
+    Paused { operation: String },
+
+    // migration errors
+    #[error("Cannot migrate from contract {contract}")]
+    InvalidMigrationContract { contract: String },
+
+    #[error("Cannot migrate from version {stored_version} to older version {new_version}")]
+    CannotMigrateToOlderVersion {
+        stored_version: String,
+        new_version: String,
+    },
+
+    #[error("An admin must be provided to migrate from version {stored_version}")]
+    MigrationAdminRequired { stored_version: String },
+
+    // single sided deposits
+    #[error("No swap contract is configured")]
+    SwapNotConfigured {},
+
+    #[error("Single sided deposits require exactly two assets")]
+    SingleSidedRequiresTwoAssets {},
+
+    #[error("A swap is already in progress")]
+    SwapInProgress {},
+
+    // router operations
+    #[error("Router for {denom} rejected the bond: {error}")]
+    BondRejected { denom: String, error: String },
+
+    #[error("No operation with nonce {nonce} is waiting on this router")]
+    UnknownOperation { nonce: u64 },
+
+    #[error("Operation with nonce {nonce} has already been settled")]
+    OperationAlreadySettled { nonce: u64 },
+
+    #[error("No operation of the id is waiting on this router")]
+    NoPendingOperation {},
+
+    #[error("Unbond response must carry a single non-zero amount of {denom}")]
+    InvalidUnbondFunds { denom: String },
+
+    #[error("Unbond response settles {amount} shares, above the {expected} waiting on the router")]
+    UnbondFundsTooHigh { amount: Coin, expected: Coin },
+
+    #[error("Returned {amount} does not match the {expected} sent to the router")]
+    InvalidReturnedAmount { amount: Coin, expected: Coin },
+
+    #[error("A failed unbonding cannot carry funds, its shares are rolled back")]
+    UnexpectedFunds {},
+
     // logic flow
+    #[error("No bond of the id has been waiting on its router for longer than the timeout")]
+    NoStaleBond {},
+
     #[error("Cannot start unbonding if existing unconfirmed unbonding")]
     NoStartUnbondingIfExistingUnconfirmed {},
 }
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
+}
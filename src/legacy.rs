@@ -0,0 +1,52 @@
+//! Storage layouts of previous contract versions, only used by migrations
+
+use cosmwasm_std::{Addr, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub mod v0_1 {
+    use super::*;
+
+    /// Config storage, before the admin was added
+    pub const CONFIG: Item<Config> = Item::new("config");
+
+    /// Track which address made which bonding operation, before the owner index was added
+    pub const ID_TO_ADDRESS_TRACKER: Map<String, Addr> = Map::new("id_to_address_tracker");
+
+    /// map id to bond_status
+    pub const BOND_STATUS_TRACKER: Map<String, BondStatus> = Map::new("bond_status_tracker");
+
+    #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+    #[serde(rename_all = "snake_case")]
+    pub struct Config {
+        pub lock_period_denom_1: u64,
+        pub lock_period_denom_2: u64,
+        pub denom_1: String,
+        pub router_denom_1: Addr,
+        pub denom_2: String,
+        pub router_denom_2: Addr,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+    pub struct BondStatusData {
+        pub denom_1: Uint128,
+        pub denom_2: Uint128,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+    pub struct UnbondingElement {
+        pub denom: String,
+        pub value: Uint128,
+        pub unbonding_start_time: Timestamp,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+    pub struct BondStatus {
+        pub sent_to_bond: BondStatusData,
+        pub bonded: BondStatusData,
+        pub unconfirmed_unbonding: BondStatusData,
+        pub unbonding: Vec<UnbondingElement>,
+        pub sent_for_unbond: BondStatusData,
+    }
+}
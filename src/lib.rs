@@ -1,11 +1,17 @@
+#![allow(clippy::needless_return)]
+
 pub mod contract;
 mod error;
+mod legacy;
 pub mod msg;
 pub mod state;
 pub mod typing;
 
+pub mod contract_admin;
 pub mod contract_callback;
 pub mod contract_execute;
+pub mod contract_migrate;
 pub mod contract_query;
+pub mod contract_reply;
 
 pub use crate::error::ContractError;
@@ -1,30 +1,72 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Uint128};
+use cw_utils::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::typing::{BondStatus, Config};
+use crate::typing::{
+    Approval, BondStatus, Config, ExchangeRate, IdValidation, PauseState, PendingAdmin,
+    PendingOperation, RatioSource, Totals,
+};
 
 #[cw_serde]
 pub struct InstantiateMsg {
     pub config: Config,
 }
 
+#[cw_serde]
+pub struct MigrateMsg {
+    /// Admin to set when migrating from a version whose config had no admin
+    pub admin: Option<String>,
+}
+
+/// Callbacks of the routers, either tagged with their type or in the untagged format of routers
+/// written for v0.1
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
-#[serde(rename_all = "snake_case")]
 #[serde(untagged)]
+pub enum CallbackMsg {
+    Tagged(Callback),
+    Legacy(LegacyCallback),
+}
+
+impl From<Callback> for CallbackMsg {
+    fn from(callback: Callback) -> Self {
+        return CallbackMsg::Tagged(callback);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
 pub enum Callback {
     BondResponse(BondResponse),
     StartUnbondResponse(StartUnbondResponse),
     UnbondResponse(UnbondResponse),
+    ReturnBondResponse(ReturnBondResponse),
+    BondFailed(BondFailure),
+    StartUnbondFailed(StartUnbondFailure),
+    UnbondFailed(UnbondFailure),
+}
+
+/// Untagged callbacks, the start of an unbonding and its completion have the same shape and are
+/// told apart by the funds attached to the completion
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(untagged)]
+pub enum LegacyCallback {
+    BondResponse(BondResponse),
+    UnbondResponse(UnbondResponse),
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 /// BondResponse is the response of a the strategy once the funds are succesfully bonded
 pub struct BondResponse {
+    /// Shares received for the deposited tokens
     pub share_amount: Uint128,
     pub bond_id: String,
+    /// Nonce of the message sent to the router, the oldest operation of the router on the id is
+    /// settled without it
+    #[serde(default)]
+    pub nonce: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -32,20 +74,168 @@ pub struct BondResponse {
 /// UnbondResponse is the response of a strategy once shares succesfully start unbonding
 pub struct StartUnbondResponse {
     pub unbond_id: String,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct UnbondResponse {
     pub unbond_id: String,
+    /// Shares the returned funds were withdrawn from, the amount of the funds if unset as for
+    /// v0.1 routers, which are 1:1
+    #[serde(default)]
+    pub share_amount: Option<Uint128>,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+/// ReturnBondResponse sends back the funds of a bond the owner reclaimed
+pub struct ReturnBondResponse {
+    pub bond_id: String,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+/// BondFailure is sent by a strategy that could not bond the funds, returning them
+pub struct BondFailure {
+    pub bond_id: String,
+    pub reason: String,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+/// StartUnbondFailure is sent by a strategy that could not start unbonding the shares
+pub struct StartUnbondFailure {
+    pub unbond_id: String,
+    pub reason: String,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+/// UnbondFailure is sent by a strategy that could not withdraw the unbonded shares
+pub struct UnbondFailure {
+    pub unbond_id: String,
+    pub reason: String,
+    /// Nonce of the message sent to the router, as in BondResponse
+    #[serde(default)]
+    pub nonce: Option<u64>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    Bond { id: String },
-    StartUnbond { id: String, share_amount: Uint128 },
-    Unbond { id: String, share_amount: Uint128 },
-    Callback(Callback),
+    /// Without an id, one is generated and returned in the data as BondData
+    Bond {
+        id: Option<String>,
+    },
+    /// Deposit a single denom, half of it is swapped into the other denom before bonding
+    BondSingleSided {
+        id: Option<String>,
+        min_out: Uint128,
+    },
+    /// Shares are either the same `share_amount` for every denom, or per denom in `share_amounts`
+    StartUnbond {
+        id: String,
+        share_amount: Option<Uint128>,
+        share_amounts: Option<Vec<Coin>>,
+    },
+    /// Funds go to `recipient`, the owner by default, executing `msg` on it if set
+    Unbond {
+        id: String,
+        share_amount: Option<Uint128>,
+        share_amounts: Option<Vec<Coin>>,
+        recipient: Option<String>,
+        msg: Option<Binary>,
+    },
+    /// Move the position of the id, with all its balances, to another owner
+    TransferId {
+        id: String,
+        new_owner: String,
+    },
+    /// Ask the routers to return the funds of bonds they have not confirmed within the bond
+    /// timeout, the funds are sent to the owner once returned
+    ReclaimStaleBond {
+        id: String,
+    },
+    /// Remove a position with nothing left in it, freeing the id
+    CloseId {
+        id: String,
+    },
+    /// Allow an operator to start unbonding and unbond the id, funds are still sent to the owner
+    /// unless `allow_custom_recipient` is set
+    Approve {
+        id: String,
+        operator: String,
+        expires: Option<Expiration>,
+        allow_custom_recipient: Option<bool>,
+    },
+    Revoke {
+        id: String,
+        operator: String,
+    },
+    /// Allow an operator to start unbonding and unbond every id of the caller
+    ApproveAll {
+        operator: String,
+        expires: Option<Expiration>,
+        allow_custom_recipient: Option<bool>,
+    },
+    RevokeAll {
+        operator: String,
+    },
+    Callback(CallbackMsg),
+
+    // admin
+    UpdateConfig {
+        assets: Vec<AssetConfigUpdate>,
+        ratio_source: Option<RatioSource>,
+        ratio_tolerance: Option<Decimal>,
+        refund_excess: Option<bool>,
+        swap_contract: Option<String>,
+        id_validation: Option<IdValidation>,
+        bond_timeout: Option<u64>,
+    },
+    ProposeNewAdmin {
+        new_admin: String,
+        expires: Option<Expiration>,
+    },
+    AcceptAdmin {},
+    CancelAdminTransfer {},
+    SetGuardian {
+        guardian: Option<String>,
+    },
+    SetPauseState {
+        bonding: Option<bool>,
+        unbonding: Option<bool>,
+        callbacks: Option<bool>,
+    },
+}
+
+/// Data of the responses to Bond and BondSingleSided
+#[cw_serde]
+pub struct BondData {
+    pub id: String,
+}
+
+/// Changes to the strategy of a configured denom, unset fields are left unchanged
+#[cw_serde]
+pub struct AssetConfigUpdate {
+    pub denom: String,
+    pub router: Option<String>,
+    pub lock_period: Option<u64>,
+    pub ratio: Option<Decimal>,
 }
 
 #[cw_serde]
@@ -54,11 +244,58 @@ pub enum QueryMsg {
     #[returns(GetConfigResponse)]
     GetConfig {},
 
+    #[returns(GetPendingAdminResponse)]
+    GetPendingAdmin {},
+
+    #[returns(GetPauseStateResponse)]
+    GetPauseState {},
+
     #[returns(GetBondStatusResponse)]
     GetBondStatus { id: String },
 
+    #[returns(GetTotalsResponse)]
+    GetTotals {},
+
+    #[returns(ListBondStatusesResponse)]
+    ListBondStatuses {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(GetIdsByOwnerResponse)]
+    GetIdsByOwner {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(GetApprovalsResponse)]
+    GetApprovals { id: String },
+
+    #[returns(GetOperatorsResponse)]
+    GetOperators { owner: String },
+
     #[returns(GetSharesAvailableUnbondResponse)]
     GetSharesAvailableUnbond { id: String },
+
+    /// Value in tokens of the position of the id, the routers converting its shares, or the
+    /// stored exchange rates for routers that cannot
+    #[returns(GetPositionValueResponse)]
+    GetPositionValue { id: String },
+
+    #[returns(GetExchangeRatesResponse)]
+    GetExchangeRates {},
+
+    /// Operations still waiting on a router confirmation, ordered by nonce. `older_than` keeps the
+    /// operations sent at least that many seconds ago
+    #[returns(ListPendingOperationsResponse)]
+    ListPendingOperations {
+        id: Option<String>,
+        router: Option<String>,
+        older_than: Option<u64>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 #[cw_serde]
@@ -66,19 +303,134 @@ pub struct GetConfigResponse {
     pub config: Config,
 }
 
+#[cw_serde]
+pub struct GetPendingAdminResponse {
+    pub pending_admin: Option<PendingAdmin>,
+}
+
+#[cw_serde]
+pub struct GetPauseStateResponse {
+    pub pause_state: PauseState,
+}
+
 #[cw_serde]
 pub struct GetBondStatusResponse {
     pub bond_status: Option<BondStatus>,
 }
 
+#[cw_serde]
+pub struct GetTotalsResponse {
+    pub totals: Totals,
+}
+
+#[cw_serde]
+pub struct BondStatusEntry {
+    pub id: String,
+    pub owner: Addr,
+    pub bond_status: BondStatus,
+}
+
+#[cw_serde]
+pub struct ListBondStatusesResponse {
+    pub bond_statuses: Vec<BondStatusEntry>,
+}
+
+#[cw_serde]
+pub struct GetIdsByOwnerResponse {
+    pub bond_statuses: Vec<BondStatusEntry>,
+}
+
+#[cw_serde]
+pub struct GetApprovalsResponse {
+    pub approvals: Vec<Approval>,
+}
+
+#[cw_serde]
+pub struct GetOperatorsResponse {
+    pub operators: Vec<Approval>,
+}
+
 #[cw_serde]
 pub struct GetSharesAvailableUnbondResponse {
+    /// Amount that can be unbonded on every denom at once
     pub shares_available_unbond: Uint128,
+    pub shares_available_unbond_per_denom: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct GetPositionValueResponse {
+    pub values: Vec<PositionValue>,
+}
+
+#[cw_serde]
+pub struct PositionValue {
+    pub denom: String,
+    /// Shares held by the router for the position, at any stage
+    pub shares: Uint128,
+    /// Tokens the shares convert to
+    pub shares_value: Uint128,
+    /// Tokens not converted to shares yet
+    pub sent_to_bond: Uint128,
+    pub value: Uint128,
+}
+
+#[cw_serde]
+pub struct GetExchangeRatesResponse {
+    pub exchange_rates: Vec<ExchangeRate>,
+}
+
+#[cw_serde]
+pub struct ListPendingOperationsResponse {
+    pub pending_operations: Vec<PendingOperation>,
 }
 
 #[cw_serde]
+/// Messages sent to routers carry the nonce of the operation, to echo back in the callback
 pub enum ExternalExecuteMsg {
-    OnBond { id: String },
-    OnStartUnbond { id: String, share_amount: Uint128 },
-    OnUnbond { id: String, share_amount: Uint128 },
+    OnBond {
+        id: String,
+        nonce: u64,
+    },
+    OnStartUnbond {
+        id: String,
+        share_amount: Uint128,
+        nonce: u64,
+    },
+    OnUnbond {
+        id: String,
+        share_amount: Uint128,
+        nonce: u64,
+    },
+    /// Return the funds of the bond sent with the nonce
+    ReturnBond {
+        id: String,
+        amount: Uint128,
+        nonce: u64,
+    },
+    Swap {
+        ask_denom: String,
+        min_out: Uint128,
+    },
+}
+
+#[cw_serde]
+pub enum ExternalQueryMsg {
+    Price { denom: String },
+    Simulation { offer: Coin, ask_denom: String },
+    ConvertToAssets { shares: Uint128 },
+}
+
+#[cw_serde]
+pub struct PriceResponse {
+    pub price: Decimal,
+}
+
+#[cw_serde]
+pub struct ConvertToAssetsResponse {
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
 }
@@ -1,13 +1,133 @@
-use cosmwasm_std::Addr;
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, StdResult, Storage};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, UniqueIndex};
 
-use crate::typing::{BondStatus, Config};
+use crate::{
+    typing::{
+        Approval, BondStatus, Config, ExchangeRate, PauseState, PendingAdmin, PendingOperation,
+        PendingSwap, Totals,
+    },
+    ContractError,
+};
 
 /// Config storage
 pub const CONFIG: Item<Config> = Item::new("config");
 
-/// Track which address made which bonding operation
-pub const ID_TO_ADDRESS_TRACKER: Map<String, Addr> = Map::new("id_to_address_tracker");
+/// Admin transfer awaiting acceptance by the proposed address
+pub const PENDING_ADMIN: Item<PendingAdmin> = Item::new("pending_admin");
+
+/// Operations currently paused, nothing is paused if unset
+pub const PAUSE_STATE: Item<PauseState> = Item::new("pause_state");
+
+/// Single sided deposit being swapped, only set between the swap and its reply
+pub const PENDING_SWAP: Item<PendingSwap> = Item::new("pending_swap");
+
+/// Next id to try when generating one for a bond without an id
+pub const NEXT_ID: Item<u64> = Item::new("next_id");
+
+/// Secondary indexes of the id owners
+pub struct IdOwnerIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, Addr, String>,
+}
+
+impl<'a> IndexList<Addr> for IdOwnerIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Addr>> + '_> {
+        let v: Vec<&dyn Index<Addr>> = vec![&self.owner];
+        return Box::new(v.into_iter());
+    }
+}
+
+/// Track which address made which bonding operation, indexed by owner
+pub fn id_to_address_tracker<'a>() -> IndexedMap<'a, String, Addr, IdOwnerIndexes<'a>> {
+    let indexes = IdOwnerIndexes {
+        owner: MultiIndex::new(
+            |_pk, owner| owner.clone(),
+            "id_to_address_tracker",
+            "id_to_address_tracker__owner",
+        ),
+    };
+    return IndexedMap::new("id_to_address_tracker", indexes);
+}
+
+/// Operators approved for a single id, keyed by (id, operator)
+pub const APPROVALS: Map<(String, Addr), Approval> = Map::new("approvals");
+
+/// Operators approved for every id of an owner, keyed by (owner, operator)
+pub const OPERATORS: Map<(Addr, Addr), Approval> = Map::new("operators");
+
+/// Next reply id to assign to a message sent to a router
+pub const NEXT_REPLY_ID: Item<u64> = Item::new("next_reply_id");
+
+/// Key of a pending operation: the id, the router it was sent to and its nonce
+pub type PendingOperationKey = (String, Addr, u64);
+
+/// Secondary indexes of the pending operations
+pub struct PendingOperationIndexes<'a> {
+    pub nonce: UniqueIndex<'a, u64, PendingOperation, PendingOperationKey>,
+    /// Operations of each router, ordered by nonce
+    pub router: MultiIndex<'a, (Addr, u64), PendingOperation, PendingOperationKey>,
+}
+
+impl<'a> IndexList<PendingOperation> for PendingOperationIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<PendingOperation>> + '_> {
+        let v: Vec<&dyn Index<PendingOperation>> = vec![&self.nonce, &self.router];
+        return Box::new(v.into_iter());
+    }
+}
+
+/// Operations sent to the routers and not confirmed yet, indexed by nonce and by router
+pub fn pending_operations<'a>(
+) -> IndexedMap<'a, PendingOperationKey, PendingOperation, PendingOperationIndexes<'a>> {
+    let indexes = PendingOperationIndexes {
+        nonce: UniqueIndex::new(|operation| operation.nonce, "pending_operations__nonce"),
+        router: MultiIndex::new(
+            |_pk, operation| (operation.router.clone(), operation.nonce),
+            "pending_operations",
+            "pending_operations__router",
+        ),
+    };
+    return IndexedMap::new("pending_operations", indexes);
+}
+
+/// Load the pending operation sent with the nonce, if it has not been settled
+pub fn load_pending_operation(
+    storage: &dyn Storage,
+    nonce: u64,
+) -> StdResult<Option<PendingOperation>> {
+    return Ok(pending_operations()
+        .idx
+        .nonce
+        .item(storage, nonce)?
+        .map(|(_, operation)| operation));
+}
 
 /// map id to bond_status
 pub const BOND_STATUS_TRACKER: Map<String, BondStatus> = Map::new("bond_status_tracker");
+
+/// Exchange rate between tokens and shares of each router
+pub const EXCHANGE_RATES: Map<Addr, ExchangeRate> = Map::new("exchange_rates");
+
+/// Amounts of all positions added together, kept in sync by update_bond_status
+pub const TOTALS: Item<Totals> = Item::new("totals");
+
+/// Update a bond status like Map::update, applying the change to the totals as well
+pub fn update_bond_status<A>(
+    storage: &mut dyn Storage,
+    id: String,
+    action: A,
+) -> Result<BondStatus, ContractError>
+where
+    A: FnOnce(Option<BondStatus>) -> Result<BondStatus, ContractError>,
+{
+    let old = BOND_STATUS_TRACKER.may_load(storage, id.clone())?;
+    let mut totals = TOTALS.may_load(storage)?.unwrap_or_default();
+    if let Some(old) = &old {
+        totals.sub_bond_status(old)?;
+    }
+
+    let new = action(old)?;
+    totals.add_bond_status(&new)?;
+
+    BOND_STATUS_TRACKER.save(storage, id, &new)?;
+    TOTALS.save(storage, &totals)?;
+    return Ok(new);
+}
@@ -1,49 +1,326 @@
-use cosmwasm_std::{Addr, Timestamp, Uint128};
+use cosmwasm_std::{Addr, Api, Binary, Coin, Decimal, StdResult, Timestamp, Uint128};
+use cw_utils::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::ContractError;
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct Config {
-    /// The lock period of the strategy for denom 1
-    pub lock_period_denom_1: u64,
-    /// The lock period of the strategy for denom 2
-    pub lock_period_denom_2: u64,
-    /// The first allowed denom for bonding
-    pub denom_1: String,
-    /// Router to stake denom_1
-    pub router_denom_1: Addr,
-    /// The second allowed denom for bonding
-    pub denom_2: String,
-    /// Router to stake denom_1
-    pub router_denom_2: Addr,
+    /// Address allowed to update the config
+    pub admin: Addr,
+    /// Address allowed to pause the contract in an emergency, but not to unpause it
+    pub guardian: Option<Addr>,
+    /// The allowed denoms for bonding, with the strategy handling each of them
+    pub assets: Vec<AssetConfig>,
+    /// Where the ratios between the deposited amounts come from
+    #[serde(default)]
+    pub ratio_source: RatioSource,
+    /// Relative difference allowed between deposited amounts once normalized by their ratio
+    #[serde(default)]
+    pub ratio_tolerance: Decimal,
+    /// Bond the largest balanced portion of unbalanced deposits and refund the rest, instead of
+    /// rejecting them
+    #[serde(default)]
+    pub refund_excess: bool,
+    /// Contract used to swap half of single sided deposits into the other denom
+    #[serde(default)]
+    pub swap_contract: Option<Addr>,
+    /// Rules for the ids chosen by users
+    #[serde(default)]
+    pub id_validation: IdValidation,
+    /// Seconds after which a bond the router has not confirmed can be reclaimed by its owner
+    #[serde(default = "default_bond_timeout")]
+    pub bond_timeout: u64,
+}
+
+/// One week
+pub const DEFAULT_BOND_TIMEOUT: u64 = 7 * 24 * 60 * 60;
+
+fn default_bond_timeout() -> u64 {
+    return DEFAULT_BOND_TIMEOUT;
 }
 
 impl Config {
     pub fn is_valid_denom(&self, denom: &str) -> bool {
-        return self.denom_1 == denom || self.denom_2 == denom;
+        return self.get_asset(denom).is_some();
     }
 
     pub fn is_valid_callback_caller(&self, caller: &Addr) -> bool {
-        return self.router_denom_1.eq(caller) || self.router_denom_2.eq(caller);
+        return self.get_asset_by_router(caller).is_some();
+    }
+
+    pub fn get_asset(&self, denom: &str) -> Option<&AssetConfig> {
+        return self.assets.iter().find(|asset| asset.denom == denom);
+    }
+
+    pub fn get_asset_by_router(&self, router: &Addr) -> Option<&AssetConfig> {
+        return self.assets.iter().find(|asset| asset.router.eq(router));
+    }
+
+    /// Check that addresses are valid, and that denoms and routers are unique so callbacks
+    /// can be attributed to a single denom
+    pub fn validate(&self, api: &dyn Api) -> Result<(), ContractError> {
+        api.addr_validate(self.admin.as_str())?;
+        if let Some(guardian) = &self.guardian {
+            api.addr_validate(guardian.as_str())?;
+        }
+
+        if self.assets.is_empty() {
+            return Err(ContractError::NoAssets {});
+        }
+
+        if let Some(swap_contract) = &self.swap_contract {
+            api.addr_validate(swap_contract.as_str())?;
+        }
+
+        if let RatioSource::Oracle { contract } = &self.ratio_source {
+            api.addr_validate(contract.as_str())?;
+        }
+
+        for (index, asset) in self.assets.iter().enumerate() {
+            api.addr_validate(asset.router.as_str())?;
+
+            if matches!(self.ratio_source, RatioSource::Static {}) && asset.ratio.is_zero() {
+                return Err(ContractError::InvalidRatio {
+                    denom: asset.denom.to_owned(),
+                });
+            }
+
+            for other in &self.assets[index + 1..] {
+                if other.denom == asset.denom {
+                    return Err(ContractError::DuplicateDenom {
+                        denom: asset.denom.to_owned(),
+                    });
+                } else if other.router == asset.router {
+                    return Err(ContractError::RoutersAreSame {});
+                }
+            }
+        }
+
+        if self.id_validation.min_length > self.id_validation.max_length {
+            return Err(ContractError::InvalidIdValidation {});
+        }
+
+        return Ok(());
     }
 }
 
+/// Rules applied to ids chosen by users, generated ids are not checked
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct IdValidation {
+    pub min_length: u32,
+    pub max_length: u32,
+    /// Characters allowed in ids, any character if unset
+    pub allowed_chars: Option<String>,
+    /// Prefixes ids cannot start with
+    pub reserved_prefixes: Vec<String>,
+}
+
+impl Default for IdValidation {
+    fn default() -> Self {
+        return IdValidation {
+            min_length: 1,
+            max_length: 64,
+            allowed_chars: None,
+            reserved_prefixes: vec![],
+        };
+    }
+}
+
+impl IdValidation {
+    pub fn validate(&self, id: &str) -> Result<(), ContractError> {
+        let length = id.chars().count();
+        if length < self.min_length as usize || length > self.max_length as usize {
+            return Err(ContractError::InvalidId {
+                reason: format!(
+                    "length must be between {} and {}",
+                    self.min_length, self.max_length
+                ),
+            });
+        }
+
+        if let Some(allowed_chars) = &self.allowed_chars {
+            if let Some(c) = id.chars().find(|c| !allowed_chars.contains(*c)) {
+                return Err(ContractError::InvalidId {
+                    reason: format!("character {:?} is not allowed", c),
+                });
+            }
+        }
+
+        if let Some(prefix) = self
+            .reserved_prefixes
+            .iter()
+            .find(|prefix| id.starts_with(prefix.as_str()))
+        {
+            return Err(ContractError::InvalidId {
+                reason: format!("prefix {} is reserved", prefix),
+            });
+        }
+
+        return Ok(());
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct AssetConfig {
+    /// The allowed denom for bonding
+    pub denom: String,
+    /// Router to stake the denom
+    pub router: Addr,
+    /// The lock period of the strategy for the denom
+    pub lock_period: u64,
+    /// Share of the denom in a deposit when ratios are static, 1 for every denom means 1:1
+    #[serde(default = "Decimal::one")]
+    pub ratio: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RatioSource {
+    /// Deposited amounts must follow the ratio of each asset
+    Static {},
+    /// Deposited amounts must have the same value, using the prices returned by the oracle
+    Oracle { contract: Addr },
+}
+
+impl Default for RatioSource {
+    fn default() -> Self {
+        return RatioSource::Static {};
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingAdmin {
+    /// Address proposed as the new admin, must accept to become admin
+    pub new_admin: Addr,
+    /// After this point the proposal cannot be accepted anymore
+    pub expires: Expiration,
+}
+
+/// Single sided deposit waiting for its swap to complete
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingSwap {
+    pub id: String,
+    pub owner: Addr,
+    /// Part of the deposit that was not swapped
+    pub kept: Coin,
+    pub ask_denom: String,
+    pub min_out: Uint128,
+    /// Contract balance of the ask denom before the swap
+    pub balance_before: Uint128,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
-pub struct BondStatusData {
-    pub denom_1: Uint128,
-    pub denom_2: Uint128,
+#[serde(rename_all = "snake_case")]
+pub struct PauseState {
+    /// Blocks new Bond calls
+    pub bonding: bool,
+    /// Blocks new StartUnbond and Unbond calls
+    pub unbonding: bool,
+    /// Blocks callbacks from the routers
+    pub callbacks: bool,
+}
+
+/// Operator allowed to start unbonding and unbond on behalf of the owner until it expires
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Approval {
+    pub operator: Addr,
+    pub expires: Expiration,
+    /// Whether the operator can send unbonded funds elsewhere than to the owner
+    #[serde(default)]
+    pub allow_custom_recipient: bool,
+}
+
+/// Destination of the funds of an unbonding
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Withdrawal {
+    /// The owner of the id when the funds arrive if unset
+    pub recipient: Option<Addr>,
+    /// Message executed on the recipient with the funds attached, instead of a bank send
+    pub msg: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Bond,
+    StartUnbond,
+    Unbond,
+}
+
+/// Message sent to a router, tracked until the router confirms it with a callback
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct PendingOperation {
+    /// Nonce sent to the router, also the reply id of the message
+    pub nonce: u64,
+    pub id: String,
+    pub router: Addr,
+    pub denom: String,
+    pub kind: OperationKind,
+    /// Tokens sent for bonds, shares for unbondings
+    pub amount: Uint128,
+    pub dispatched_at: Timestamp,
+    /// Whether the router executed the message, set by its reply
+    pub accepted: bool,
+    /// Whether the owner asked the router to return the funds of the bond
+    #[serde(default)]
+    pub reclaimed: bool,
+    /// Where the funds of an unbonding go, the owner of the id if unset
+    #[serde(default)]
+    pub withdrawal: Option<Withdrawal>,
+}
+
+impl PendingOperation {
+    pub fn key(&self) -> (String, Addr, u64) {
+        return (self.id.clone(), self.router.clone(), self.nonce);
+    }
 }
 
+/// Amounts per denom for a given stage of a position
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct BondStatusData(pub Vec<Coin>);
 
 impl BondStatusData {
-    /// Create a new BondStatusData from a single value.
-    /// To be used for a new sent_to_bond element in BondStatus since funds are presumed to be equal
-    pub fn new(value: Uint128) -> Self {
-        return BondStatusData {
-            denom_1: value,
-            denom_2: value,
-        };
+    /// Amount tracked for the denom, zero if the denom has never been used
+    pub fn get(&self, denom: &str) -> Uint128 {
+        return self
+            .0
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+    }
+
+    pub fn set(&mut self, denom: &str, amount: Uint128) {
+        match self.0.iter_mut().find(|coin| coin.denom == denom) {
+            Some(coin) => coin.amount = amount,
+            None => self.0.push(Coin {
+                denom: denom.to_owned(),
+                amount,
+            }),
+        }
+    }
+
+    pub fn add(&mut self, denom: &str, amount: Uint128) -> StdResult<()> {
+        let total = self.get(denom).checked_add(amount)?;
+        self.set(denom, total);
+        return Ok(());
+    }
+
+    pub fn sub(&mut self, denom: &str, amount: Uint128) -> StdResult<()> {
+        let total = self.get(denom).checked_sub(amount)?;
+        self.set(denom, total);
+        return Ok(());
+    }
+
+    pub fn is_zero(&self) -> bool {
+        return self.0.iter().all(|coin| coin.amount.is_zero());
     }
 }
 
@@ -62,3 +339,75 @@ pub struct BondStatus {
     pub unbonding: Vec<UnbondingElement>,
     pub sent_for_unbond: BondStatusData,
 }
+
+impl BondStatus {
+    /// Whether nothing is left in any stage of the position
+    pub fn is_empty(&self) -> bool {
+        return self.sent_to_bond.is_zero()
+            && self.bonded.is_zero()
+            && self.unconfirmed_unbonding.is_zero()
+            && self.unbonding.iter().all(|elem| elem.value.is_zero())
+            && self.sent_for_unbond.is_zero();
+    }
+}
+
+/// Tokens deposited into a router and shares received for them, through confirmed bonds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ExchangeRate {
+    pub denom: String,
+    pub tokens: Uint128,
+    pub shares: Uint128,
+    /// Tokens per share implied by the latest confirmed bond
+    pub rate: Decimal,
+    pub updated_at: Timestamp,
+}
+
+/// Amounts of every position added together, per lifecycle stage
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub struct Totals {
+    pub sent_to_bond: BondStatusData,
+    pub bonded: BondStatusData,
+    pub unconfirmed_unbonding: BondStatusData,
+    pub unbonding: BondStatusData,
+    pub sent_for_unbond: BondStatusData,
+}
+
+impl Totals {
+    pub fn add_bond_status(&mut self, bond_status: &BondStatus) -> StdResult<()> {
+        for coin in &bond_status.sent_to_bond.0 {
+            self.sent_to_bond.add(&coin.denom, coin.amount)?;
+        }
+        for coin in &bond_status.bonded.0 {
+            self.bonded.add(&coin.denom, coin.amount)?;
+        }
+        for coin in &bond_status.unconfirmed_unbonding.0 {
+            self.unconfirmed_unbonding.add(&coin.denom, coin.amount)?;
+        }
+        for elem in &bond_status.unbonding {
+            self.unbonding.add(&elem.denom, elem.value)?;
+        }
+        for coin in &bond_status.sent_for_unbond.0 {
+            self.sent_for_unbond.add(&coin.denom, coin.amount)?;
+        }
+        return Ok(());
+    }
+
+    pub fn sub_bond_status(&mut self, bond_status: &BondStatus) -> StdResult<()> {
+        for coin in &bond_status.sent_to_bond.0 {
+            self.sent_to_bond.sub(&coin.denom, coin.amount)?;
+        }
+        for coin in &bond_status.bonded.0 {
+            self.bonded.sub(&coin.denom, coin.amount)?;
+        }
+        for coin in &bond_status.unconfirmed_unbonding.0 {
+            self.unconfirmed_unbonding.sub(&coin.denom, coin.amount)?;
+        }
+        for elem in &bond_status.unbonding {
+            self.unbonding.sub(&elem.denom, elem.value)?;
+        }
+        for coin in &bond_status.sent_for_unbond.0 {
+            self.sent_for_unbond.sub(&coin.denom, coin.amount)?;
+        }
+        return Ok(());
+    }
+}
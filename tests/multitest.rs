@@ -0,0 +1,337 @@
+#![allow(clippy::needless_return)]
+
+#[cfg(test)]
+mod test {
+    use cosmwasm_std::coin;
+    use cosmwasm_std::to_binary;
+    use cosmwasm_std::Addr;
+    use cosmwasm_std::BankMsg;
+    use cosmwasm_std::Binary;
+    use cosmwasm_std::Decimal;
+    use cosmwasm_std::Deps;
+    use cosmwasm_std::DepsMut;
+    use cosmwasm_std::Empty;
+    use cosmwasm_std::Env;
+    use cosmwasm_std::MessageInfo;
+    use cosmwasm_std::Response;
+    use cosmwasm_std::StdError;
+    use cosmwasm_std::StdResult;
+    use cosmwasm_std::Uint128;
+    use cw_multi_test::App;
+    use cw_multi_test::ContractWrapper;
+    use cw_multi_test::Executor;
+    use deposit_handler::msg::ExecuteMsg;
+    use deposit_handler::msg::ExternalExecuteMsg;
+    use deposit_handler::msg::ExternalQueryMsg;
+    use deposit_handler::msg::GetBondStatusResponse;
+    use deposit_handler::msg::InstantiateMsg;
+    use deposit_handler::msg::QueryMsg;
+    use deposit_handler::msg::SimulationResponse;
+    use deposit_handler::typing::AssetConfig;
+    use deposit_handler::typing::Config;
+    use deposit_handler::typing::IdValidation;
+    use deposit_handler::typing::RatioSource;
+    use deposit_handler::typing::DEFAULT_BOND_TIMEOUT;
+    use deposit_handler::ContractError;
+
+    const DENOM_1: &str = "qusd";
+    const DENOM_2: &str = "uatom";
+
+    const INITIAL_BALANCE: u128 = 100_000;
+
+    const ADMIN: &str = "admin";
+    const USER: &str = "user";
+
+    /// Rate applied by the mock swap, in percent
+    const SWAP_RATE_PERCENT: u64 = 90;
+
+    fn mock_instantiate(
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _msg: Empty,
+    ) -> StdResult<Response> {
+        return Ok(Response::new());
+    }
+
+    /// Id whose uatom side is rejected by its router
+    const REJECTED_ID: &str = "rejected_id";
+
+    /// Router keeping the funds it receives, without sending callbacks
+    fn mock_router_execute(
+        _deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        msg: ExternalExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            ExternalExecuteMsg::OnBond { id, .. }
+                if id == REJECTED_ID && info.funds[0].denom == DENOM_2 =>
+            {
+                return Err(StdError::generic_err("strategy is full"));
+            }
+            _ => return Ok(Response::new()),
+        }
+    }
+
+    fn mock_router_query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+        return Err(StdError::generic_err("no query"));
+    }
+
+    fn swap_return(amount: Uint128) -> Uint128 {
+        return amount * Decimal::percent(SWAP_RATE_PERCENT);
+    }
+
+    /// Swap paying out the ask denom at a fixed rate
+    fn mock_swap_execute(
+        _deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        msg: ExternalExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            ExternalExecuteMsg::Swap { ask_denom, min_out } => {
+                let return_amount = swap_return(info.funds[0].amount);
+                if return_amount < min_out {
+                    return Err(StdError::generic_err("slippage"));
+                }
+
+                return Ok(Response::new().add_message(BankMsg::Send {
+                    to_address: info.sender.into_string(),
+                    amount: vec![coin(return_amount.u128(), ask_denom)],
+                }));
+            }
+            _ => return Err(StdError::generic_err("unsupported")),
+        }
+    }
+
+    fn mock_swap_query(_deps: Deps, _env: Env, msg: ExternalQueryMsg) -> StdResult<Binary> {
+        match msg {
+            ExternalQueryMsg::Simulation { offer, .. } => to_binary(&SimulationResponse {
+                return_amount: swap_return(offer.amount),
+            }),
+            _ => return Err(StdError::generic_err("unsupported")),
+        }
+    }
+
+    struct Suite {
+        app: App,
+        handler: Addr,
+        router_1: Addr,
+        router_2: Addr,
+    }
+
+    fn setup() -> Suite {
+        let mut app = App::new(|router, _, storage| {
+            router
+                .bank
+                .init_balance(
+                    storage,
+                    &Addr::unchecked(USER),
+                    vec![
+                        coin(INITIAL_BALANCE, DENOM_1),
+                        coin(INITIAL_BALANCE, DENOM_2),
+                    ],
+                )
+                .unwrap();
+        });
+
+        let router_code = app.store_code(Box::new(ContractWrapper::new(
+            mock_router_execute,
+            mock_instantiate,
+            mock_router_query,
+        )));
+        let swap_code = app.store_code(Box::new(ContractWrapper::new(
+            mock_swap_execute,
+            mock_instantiate,
+            mock_swap_query,
+        )));
+        let handler_code = app.store_code(Box::new(
+            ContractWrapper::new(
+                deposit_handler::contract::execute,
+                deposit_handler::contract::instantiate,
+                deposit_handler::contract::query,
+            )
+            .with_reply(deposit_handler::contract::reply),
+        ));
+
+        let admin = Addr::unchecked(ADMIN);
+        let router_1 = app
+            .instantiate_contract(router_code, admin.clone(), &Empty {}, &[], "router_1", None)
+            .unwrap();
+        let router_2 = app
+            .instantiate_contract(router_code, admin.clone(), &Empty {}, &[], "router_2", None)
+            .unwrap();
+        let swap = app
+            .instantiate_contract(swap_code, admin.clone(), &Empty {}, &[], "swap", None)
+            .unwrap();
+
+        // the swap needs liquidity to pay out
+        app.send_tokens(
+            Addr::unchecked(USER),
+            swap.clone(),
+            &[coin(50_000, DENOM_1), coin(50_000, DENOM_2)],
+        )
+        .unwrap();
+
+        let config = Config {
+            admin: admin.clone(),
+            guardian: None,
+            assets: vec![
+                AssetConfig {
+                    denom: DENOM_1.to_owned(),
+                    router: router_1.clone(),
+                    lock_period: 1000,
+                    ratio: Decimal::one(),
+                },
+                AssetConfig {
+                    denom: DENOM_2.to_owned(),
+                    router: router_2.clone(),
+                    lock_period: 1000,
+                    ratio: Decimal::one(),
+                },
+            ],
+            ratio_source: RatioSource::Static {},
+            ratio_tolerance: Decimal::zero(),
+            refund_excess: false,
+            swap_contract: Some(swap),
+            id_validation: IdValidation::default(),
+            bond_timeout: DEFAULT_BOND_TIMEOUT,
+        };
+        let handler = app
+            .instantiate_contract(
+                handler_code,
+                admin,
+                &InstantiateMsg { config },
+                &[],
+                "deposit_handler",
+                None,
+            )
+            .unwrap();
+
+        return Suite {
+            app,
+            handler,
+            router_1,
+            router_2,
+        };
+    }
+
+    #[test]
+    /// Test a single sided deposit swapped through the configured swap contract
+    fn bond_single_sided() {
+        let mut suite = setup();
+        let user = Addr::unchecked(USER);
+
+        // minimum output above what the swap returns
+        let msg = ExecuteMsg::BondSingleSided {
+            id: Some("test_id".into()),
+            min_out: Uint128::new(4_600),
+        };
+        let err: ContractError = suite
+            .app
+            .execute_contract(
+                user.clone(),
+                suite.handler.clone(),
+                &msg,
+                &[coin(10_000, DENOM_1)],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(
+            err,
+            ContractError::SlippageExceeded {
+                min_out: Uint128::new(4_600),
+                return_amount: Uint128::new(4_500)
+            }
+        );
+
+        // 5_000 qusd are swapped into 4_500 uatom, so 4_500 of each are bonded and 500 qusd refunded
+        let msg = ExecuteMsg::BondSingleSided {
+            id: Some("test_id".into()),
+            min_out: Uint128::new(4_000),
+        };
+        suite
+            .app
+            .execute_contract(
+                user.clone(),
+                suite.handler.clone(),
+                &msg,
+                &[coin(10_000, DENOM_1)],
+            )
+            .unwrap();
+
+        let balance = |app: &App, addr: &Addr, denom: &str| -> Uint128 {
+            return app.wrap().query_balance(addr, denom).unwrap().amount;
+        };
+        assert_eq!(balance(&suite.app, &suite.router_1, DENOM_1).u128(), 4_500);
+        assert_eq!(balance(&suite.app, &suite.router_2, DENOM_2).u128(), 4_500);
+        assert_eq!(balance(&suite.app, &suite.handler, DENOM_1).u128(), 0);
+        assert_eq!(balance(&suite.app, &suite.handler, DENOM_2).u128(), 0);
+        assert_eq!(
+            balance(&suite.app, &user, DENOM_1).u128(),
+            INITIAL_BALANCE - 50_000 - 10_000 + 500
+        );
+
+        let res: GetBondStatusResponse = suite
+            .app
+            .wrap()
+            .query_wasm_smart(
+                &suite.handler,
+                &QueryMsg::GetBondStatus {
+                    id: "test_id".into(),
+                },
+            )
+            .unwrap();
+        let bond_status = res.bond_status.unwrap();
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1).u128(), 4_500);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_2).u128(), 4_500);
+    }
+
+    #[test]
+    /// Test a deposit rejected by one of its routers is reverted on both sides
+    fn bond_rejected() {
+        let mut suite = setup();
+        let user = Addr::unchecked(USER);
+
+        let msg = ExecuteMsg::Bond {
+            id: Some(REJECTED_ID.into()),
+        };
+        let err: ContractError = suite
+            .app
+            .execute_contract(
+                user.clone(),
+                suite.handler.clone(),
+                &msg,
+                &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert!(matches!(err, ContractError::BondRejected { denom, .. } if denom == DENOM_2));
+
+        // the first router, which accepted its side, does not keep it
+        let balance = |app: &App, addr: &Addr, denom: &str| -> Uint128 {
+            return app.wrap().query_balance(addr, denom).unwrap().amount;
+        };
+        assert_eq!(balance(&suite.app, &suite.router_1, DENOM_1).u128(), 0);
+        assert_eq!(balance(&suite.app, &suite.router_2, DENOM_2).u128(), 0);
+        assert_eq!(
+            balance(&suite.app, &user, DENOM_1).u128(),
+            INITIAL_BALANCE - 50_000
+        );
+
+        let res: GetBondStatusResponse = suite
+            .app
+            .wrap()
+            .query_wasm_smart(
+                &suite.handler,
+                &QueryMsg::GetBondStatus {
+                    id: REJECTED_ID.into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(res.bond_status, None);
+    }
+}
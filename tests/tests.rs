@@ -1,47 +1,95 @@
+#![allow(clippy::needless_return)]
+
 #[cfg(test)]
 mod test {
     use cosmwasm_std::coin;
+    use cosmwasm_std::coins;
     use cosmwasm_std::from_binary;
+    use cosmwasm_std::from_slice;
     use cosmwasm_std::testing::mock_dependencies_with_balances;
     use cosmwasm_std::testing::mock_env;
     use cosmwasm_std::testing::mock_info;
     use cosmwasm_std::testing::MockApi;
     use cosmwasm_std::testing::MockQuerier;
+    use cosmwasm_std::to_binary;
     use cosmwasm_std::Addr;
+    use cosmwasm_std::BankMsg;
+    use cosmwasm_std::Binary;
+    use cosmwasm_std::ContractResult;
+    use cosmwasm_std::CosmosMsg;
+    use cosmwasm_std::Decimal;
     use cosmwasm_std::Deps;
     use cosmwasm_std::Empty;
     use cosmwasm_std::Env;
     use cosmwasm_std::MemoryStorage;
     use cosmwasm_std::OwnedDeps;
+    use cosmwasm_std::Reply;
+    use cosmwasm_std::ReplyOn;
+    use cosmwasm_std::SubMsgResponse;
+    use cosmwasm_std::SubMsgResult;
+    use cosmwasm_std::SystemResult;
     use cosmwasm_std::Uint128;
+    use cosmwasm_std::WasmMsg;
+    use cosmwasm_std::WasmQuery;
+    use cw_utils::Expiration;
     use deposit_handler::contract::execute;
     use deposit_handler::contract::instantiate;
+    use deposit_handler::contract::migrate;
     use deposit_handler::contract::query;
+    use deposit_handler::contract::reply;
+    use deposit_handler::msg::AssetConfigUpdate;
+    use deposit_handler::msg::BondData;
+    use deposit_handler::msg::BondFailure;
     use deposit_handler::msg::BondResponse;
     use deposit_handler::msg::Callback;
+    use deposit_handler::msg::ConvertToAssetsResponse;
     use deposit_handler::msg::ExecuteMsg;
+    use deposit_handler::msg::ExternalExecuteMsg;
+    use deposit_handler::msg::ExternalQueryMsg;
+    use deposit_handler::msg::GetApprovalsResponse;
     use deposit_handler::msg::GetBondStatusResponse;
+    use deposit_handler::msg::GetConfigResponse;
+    use deposit_handler::msg::GetExchangeRatesResponse;
+    use deposit_handler::msg::GetIdsByOwnerResponse;
+    use deposit_handler::msg::GetOperatorsResponse;
+    use deposit_handler::msg::GetPauseStateResponse;
+    use deposit_handler::msg::GetPendingAdminResponse;
+    use deposit_handler::msg::GetPositionValueResponse;
     use deposit_handler::msg::GetSharesAvailableUnbondResponse;
+    use deposit_handler::msg::GetTotalsResponse;
     use deposit_handler::msg::InstantiateMsg;
+    use deposit_handler::msg::ListBondStatusesResponse;
+    use deposit_handler::msg::ListPendingOperationsResponse;
+    use deposit_handler::msg::MigrateMsg;
+    use deposit_handler::msg::PriceResponse;
     use deposit_handler::msg::QueryMsg;
+    use deposit_handler::msg::ReturnBondResponse;
+    use deposit_handler::msg::StartUnbondFailure;
     use deposit_handler::msg::StartUnbondResponse;
+    use deposit_handler::msg::UnbondFailure;
     use deposit_handler::msg::UnbondResponse;
+    use deposit_handler::typing::AssetConfig;
     use deposit_handler::typing::BondStatus;
     use deposit_handler::typing::Config;
+    use deposit_handler::typing::IdValidation;
+    use deposit_handler::typing::OperationKind;
+    use deposit_handler::typing::RatioSource;
+    use deposit_handler::typing::DEFAULT_BOND_TIMEOUT;
     use deposit_handler::ContractError;
 
-    const DENOM_1: &'static str = "qusd";
-    const ROUTER_DENOM_1: &'static str = "router_qusd";
-    const DENOM_2: &'static str = "uatom";
-    const ROUTER_DENOM_2: &'static str = "router_uatom";
+    const DENOM_1: &str = "qusd";
+    const ROUTER_DENOM_1: &str = "router_qusd";
+    const DENOM_2: &str = "uatom";
+    const ROUTER_DENOM_2: &str = "router_uatom";
 
     const LOCK_PERIOD_DENOM_1: u64 = 1000;
     const LOCK_PERIOD_DENOM_2: u64 = 1000;
 
     const INITIAL_BALANCE: u128 = 100_000;
 
-    const _ADMIN: &'static str = "admin";
-    const USER: &'static str = "user";
+    const ADMIN: &str = "admin";
+    const GUARDIAN: &str = "guardian";
+    const USER: &str = "user";
 
     fn setup(config: Config) -> (OwnedDeps<MemoryStorage, MockApi, MockQuerier, Empty>, Env) {
         let mut deps = mock_dependencies_with_balances(&[(
@@ -54,7 +102,7 @@ mod test {
         let env = mock_env();
 
         // create contract
-        let msg = InstantiateMsg { config: config };
+        let msg = InstantiateMsg { config };
         instantiate(deps.as_mut(), env.to_owned(), mock_info("sender", &[]), msg).unwrap();
 
         return (deps, env);
@@ -62,12 +110,28 @@ mod test {
 
     fn get_test_config() -> Config {
         return Config {
-            lock_period_denom_1: LOCK_PERIOD_DENOM_1,
-            lock_period_denom_2: LOCK_PERIOD_DENOM_2,
-            denom_1: DENOM_1.to_owned(),
-            router_denom_1: Addr::unchecked(ROUTER_DENOM_1),
-            denom_2: DENOM_2.to_owned(),
-            router_denom_2: Addr::unchecked(ROUTER_DENOM_2),
+            admin: Addr::unchecked(ADMIN),
+            guardian: Some(Addr::unchecked(GUARDIAN)),
+            assets: vec![
+                AssetConfig {
+                    denom: DENOM_1.to_owned(),
+                    router: Addr::unchecked(ROUTER_DENOM_1),
+                    lock_period: LOCK_PERIOD_DENOM_1,
+                    ratio: Decimal::one(),
+                },
+                AssetConfig {
+                    denom: DENOM_2.to_owned(),
+                    router: Addr::unchecked(ROUTER_DENOM_2),
+                    lock_period: LOCK_PERIOD_DENOM_2,
+                    ratio: Decimal::one(),
+                },
+            ],
+            ratio_source: RatioSource::Static {},
+            ratio_tolerance: Decimal::zero(),
+            refund_excess: false,
+            swap_contract: None,
+            id_validation: IdValidation::default(),
+            bond_timeout: DEFAULT_BOND_TIMEOUT,
         };
     }
 
@@ -93,7 +157,7 @@ mod test {
         let (mut deps, env) = setup(get_test_config());
 
         let msg = ExecuteMsg::Bond {
-            id: "test_id".into(),
+            id: Some("test_id".into()),
         };
 
         // no funds attached
@@ -146,7 +210,7 @@ mod test {
 
         // try bonding assets
         let msg = ExecuteMsg::Bond {
-            id: "test_id".into(),
+            id: Some("test_id".into()),
         };
         let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
         let _res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap();
@@ -154,16 +218,17 @@ mod test {
         // check state, should have 10k sent to bond
         let bond_status = get_bond_status(&deps.as_ref(), &env);
         assert!(
-            bond_status.sent_to_bond.denom_1 == bond_status.sent_to_bond.denom_2
-                && bond_status.sent_to_bond.denom_2 == Uint128::new(10000)
+            bond_status.sent_to_bond.get(DENOM_1) == bond_status.sent_to_bond.get(DENOM_2)
+                && bond_status.sent_to_bond.get(DENOM_2) == Uint128::new(10000)
         );
 
         // assets should be bonded. Send mock callbacks from associated contracts
         let callback = Callback::BondResponse(BondResponse {
             share_amount: Uint128::from(10_000u128),
             bond_id: "test_id".to_string(),
+            nonce: None,
         });
-        let msg = ExecuteMsg::Callback(callback);
+        let msg = ExecuteMsg::Callback(callback.into());
 
         // from contract handling first denom send callback
         let msg_info = mock_info(ROUTER_DENOM_1, &[]);
@@ -172,9 +237,9 @@ mod test {
         // check state, should have 10k bonded for denom 1 and 10k sent to bond for denom 2
         let bond_status = get_bond_status(&deps.as_ref(), &env);
         assert!(
-            bond_status.sent_to_bond.denom_1 == Uint128::zero()
-                && bond_status.sent_to_bond.denom_2 == Uint128::new(10000)
-                && bond_status.bonded.denom_1 == Uint128::new(10000)
+            bond_status.sent_to_bond.get(DENOM_1) == Uint128::zero()
+                && bond_status.sent_to_bond.get(DENOM_2) == Uint128::new(10000)
+                && bond_status.bonded.get(DENOM_1) == Uint128::new(10000)
         );
 
         // from contract handling second denom
@@ -184,16 +249,17 @@ mod test {
         // again check state, should have 10k bonded for denom 1 and denom 2
         let bond_status = get_bond_status(&deps.as_ref(), &env);
         assert!(
-            bond_status.sent_to_bond.denom_1 == Uint128::zero()
-                && bond_status.sent_to_bond.denom_2 == Uint128::zero()
-                && bond_status.bonded.denom_1 == Uint128::new(10000)
-                && bond_status.bonded.denom_2 == Uint128::new(10000)
+            bond_status.sent_to_bond.get(DENOM_1) == Uint128::zero()
+                && bond_status.sent_to_bond.get(DENOM_2) == Uint128::zero()
+                && bond_status.bonded.get(DENOM_1) == Uint128::new(10000)
+                && bond_status.bonded.get(DENOM_2) == Uint128::new(10000)
         );
 
         // now start unbonding
         let msg = ExecuteMsg::StartUnbond {
             id: "test_id".into(),
-            share_amount: Uint128::from(500u128),
+            share_amount: Some(Uint128::from(500u128)),
+            share_amounts: None,
         };
         let msg_info = mock_info(USER, &[]);
         let _res = execute(deps.as_mut(), env.clone(), msg_info.clone(), msg.clone()).unwrap();
@@ -201,16 +267,17 @@ mod test {
         // checking state, we should have 500 in unconfirmed unbonding
         let bond_status = get_bond_status(&deps.as_ref(), &env);
         assert!(
-            bond_status.unconfirmed_unbonding.denom_1 == Uint128::new(500)
-                && bond_status.unconfirmed_unbonding.denom_1 == Uint128::new(500)
-                && bond_status.bonded.denom_1 == Uint128::new(10000 - 500)
-                && bond_status.bonded.denom_2 == Uint128::new(10000 - 500)
+            bond_status.unconfirmed_unbonding.get(DENOM_1) == Uint128::new(500)
+                && bond_status.unconfirmed_unbonding.get(DENOM_1) == Uint128::new(500)
+                && bond_status.bonded.get(DENOM_1) == Uint128::new(10000 - 500)
+                && bond_status.bonded.get(DENOM_2) == Uint128::new(10000 - 500)
         );
 
         // we can only process one start unbond at a time. New execute should return an error
         let msg = ExecuteMsg::StartUnbond {
             id: "test_id".into(),
-            share_amount: Uint128::from(500u128),
+            share_amount: Some(Uint128::from(500u128)),
+            share_amounts: None,
         };
         let msg_info = mock_info(USER, &[]);
         let _res = execute(deps.as_mut(), env.clone(), msg_info.clone(), msg.clone()).unwrap_err();
@@ -218,8 +285,9 @@ mod test {
         // send callbacks
         let callback = Callback::StartUnbondResponse(StartUnbondResponse {
             unbond_id: "test_id".into(),
+            nonce: None,
         });
-        let msg = ExecuteMsg::Callback(callback);
+        let msg = ExecuteMsg::Callback(callback.into());
 
         let msg_info = mock_info(ROUTER_DENOM_1, &[]);
         let _res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap();
@@ -257,7 +325,10 @@ mod test {
         // trying to unbond > 500 should return an error
         let msg = ExecuteMsg::Unbond {
             id: "test_id".into(),
-            share_amount: Uint128::new(50000),
+            share_amount: Some(Uint128::new(50000)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
         };
 
         let msg_info = mock_info(USER, &[]);
@@ -266,7 +337,10 @@ mod test {
         // finish unbonding and get the tokens back
         let msg = ExecuteMsg::Unbond {
             id: "test_id".into(),
-            share_amount: Uint128::from(250u128),
+            share_amount: Some(Uint128::from(250u128)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
         };
 
         let msg_info = mock_info(USER, &[]);
@@ -279,15 +353,17 @@ mod test {
             bond_status.unbonding.len() == 2
             && bond_status.unbonding[0].value == Uint128::new(250)
             // we should have 250 shares unconfirmed for sent for unbond (so waiting for transfer)
-            && bond_status.sent_for_unbond.denom_1 == Uint128::new(250)
-            && bond_status.sent_for_unbond.denom_2 == Uint128::new(250)
+            && bond_status.sent_for_unbond.get(DENOM_1) == Uint128::new(250)
+            && bond_status.sent_for_unbond.get(DENOM_2) == Uint128::new(250)
         );
 
         // final callbacks sending funds back to the user
         let callback = Callback::UnbondResponse(UnbondResponse {
             unbond_id: "test_id".into(),
+            share_amount: None,
+            nonce: None,
         });
-        let msg = ExecuteMsg::Callback(callback);
+        let msg = ExecuteMsg::Callback(callback.into());
 
         let msg_info = mock_info(ROUTER_DENOM_1, &[coin(250, DENOM_1)]);
         let _res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap();
@@ -305,8 +381,2679 @@ mod test {
             && bond_status.unbonding[1].value == Uint128::new(250)
 
             // the bonded balances should be at 9500
-            && bond_status.bonded.denom_1 == Uint128::new(9500)
-            && bond_status.bonded.denom_2 == Uint128::new(9500)
+            && bond_status.bonded.get(DENOM_1) == Uint128::new(9500)
+            && bond_status.bonded.get(DENOM_2) == Uint128::new(9500)
+        );
+    }
+
+    #[test]
+    /// Test config updates: only admin, and no router swap while operations are pending
+    fn update_config() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::UpdateConfig {
+            assets: vec![
+                AssetConfigUpdate {
+                    denom: DENOM_1.into(),
+                    router: Some("new_router_qusd".into()),
+                    lock_period: None,
+                    ratio: None,
+                },
+                AssetConfigUpdate {
+                    denom: DENOM_2.into(),
+                    router: None,
+                    lock_period: Some(2000),
+                    ratio: None,
+                },
+            ],
+            ratio_source: None,
+            ratio_tolerance: None,
+            refund_excess: Some(true),
+            swap_contract: None,
+            id_validation: None,
+            bond_timeout: None,
+        };
+
+        // only the admin can update the config
+        let msg_info = mock_info(USER, &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        // routers must stay distinct
+        let bad_msg = ExecuteMsg::UpdateConfig {
+            assets: vec![AssetConfigUpdate {
+                denom: DENOM_1.into(),
+                router: Some(ROUTER_DENOM_2.into()),
+                lock_period: None,
+                ratio: None,
+            }],
+            ratio_source: None,
+            ratio_tolerance: None,
+            refund_excess: None,
+            swap_contract: None,
+            id_validation: None,
+            bond_timeout: None,
+        };
+        let msg_info = mock_info(ADMIN, &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, bad_msg).unwrap_err();
+        assert_eq!(res, ContractError::RoutersAreSame {});
+
+        // a bond awaiting confirmation prevents swapping the router
+        let bond_msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, bond_msg).unwrap();
+
+        let msg_info = mock_info(ADMIN, &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::RouterHasPendingOperations {
+                denom: DENOM_1.into()
+            }
+        );
+
+        // shares held in the router also prevent swapping it
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::from(10_000u128),
+            bond_id: "test_id".to_string(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        let msg_info = mock_info(ADMIN, &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::RouterHasPendingOperations {
+                denom: DENOM_1.into()
+            }
+        );
+
+        // as do unbonding shares
+        let start_unbond = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(10_000)),
+            share_amounts: None,
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            start_unbond,
+        )
+        .unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        let msg_info = mock_info(ADMIN, &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::RouterHasPendingOperations {
+                denom: DENOM_1.into()
+            }
+        );
+
+        // once everything is withdrawn from the router, the swap goes through
+        env.block.time = env.block.time.plus_seconds(LOCK_PERIOD_DENOM_1 + 1);
+        let unbond = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(10_000)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), unbond).unwrap();
+        let callback = ExecuteMsg::Callback(
+            Callback::UnbondResponse(UnbondResponse {
+                unbond_id: "test_id".into(),
+                share_amount: None,
+                nonce: None,
+            })
+            .into(),
+        );
+        let msg_info = mock_info(ROUTER_DENOM_1, &[coin(10_000, DENOM_1)]);
+        execute(deps.as_mut(), env.clone(), msg_info, callback).unwrap();
+
+        let msg_info = mock_info(ADMIN, &[]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        let res: GetConfigResponse =
+            from_binary(&query(deps.as_ref(), env, QueryMsg::GetConfig {}).unwrap()).unwrap();
+        assert_eq!(
+            res.config.assets[0].router,
+            Addr::unchecked("new_router_qusd")
+        );
+        assert_eq!(res.config.assets[1].router, Addr::unchecked(ROUTER_DENOM_2));
+        assert_eq!(res.config.assets[0].lock_period, LOCK_PERIOD_DENOM_1);
+        assert_eq!(res.config.assets[1].lock_period, 2000);
+        assert!(res.config.refund_excess);
+    }
+
+    #[test]
+    /// Test the propose / accept / cancel flow of the admin transfer
+    fn admin_transfer() {
+        let (mut deps, mut env) = setup(get_test_config());
+        const NEW_ADMIN: &str = "new_admin";
+
+        // only the admin can propose
+        let msg = ExecuteMsg::ProposeNewAdmin {
+            new_admin: NEW_ADMIN.into(),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            msg.clone(),
+        );
+        assert_eq!(res.unwrap_err(), ContractError::Unauthorized {});
+
+        execute(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), msg).unwrap();
+        let res: GetPendingAdminResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetPendingAdmin {}).unwrap())
+                .unwrap();
+        assert_eq!(
+            res.pending_admin.unwrap().new_admin,
+            Addr::unchecked(NEW_ADMIN)
+        );
+
+        // only the proposed address can accept
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            ExecuteMsg::AcceptAdmin {},
+        );
+        assert_eq!(res.unwrap_err(), ContractError::Unauthorized {});
+
+        // cancelling clears the proposal
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ADMIN, &[]),
+            ExecuteMsg::CancelAdminTransfer {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(NEW_ADMIN, &[]),
+            ExecuteMsg::AcceptAdmin {},
+        );
+        assert_eq!(res.unwrap_err(), ContractError::NoPendingAdminTransfer {});
+
+        // expired proposals cannot be accepted
+        let msg = ExecuteMsg::ProposeNewAdmin {
+            new_admin: NEW_ADMIN.into(),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ADMIN, &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        let mut expired_env = env.clone();
+        expired_env.block.height += 10;
+        let res = execute(
+            deps.as_mut(),
+            expired_env,
+            mock_info(NEW_ADMIN, &[]),
+            ExecuteMsg::AcceptAdmin {},
+        );
+        assert_eq!(res.unwrap_err(), ContractError::AdminTransferExpired {});
+
+        // accepting in time transfers the admin role
+        env.block.height += 5;
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(NEW_ADMIN, &[]),
+            ExecuteMsg::AcceptAdmin {},
+        )
+        .unwrap();
+
+        let res: GetConfigResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(res.config.admin, Addr::unchecked(NEW_ADMIN));
+
+        let res: GetPendingAdminResponse =
+            from_binary(&query(deps.as_ref(), env, QueryMsg::GetPendingAdmin {}).unwrap()).unwrap();
+        assert_eq!(res.pending_admin, None);
+    }
+
+    #[test]
+    /// Test migration checks and the rewrite of v0.1 storage
+    fn migration() {
+        let (mut deps, env) = setup(get_test_config());
+
+        // refuse to migrate another contract
+        cw2::set_contract_version(deps.as_mut().storage, "other-contract", "0.1.0").unwrap();
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { admin: None }).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidMigrationContract {
+                contract: "other-contract".into()
+            }
+        );
+
+        // refuse downgrades
+        cw2::set_contract_version(deps.as_mut().storage, "deposit-handler", "99.0.0").unwrap();
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { admin: None }).unwrap_err();
+        assert!(matches!(
+            res,
+            ContractError::CannotMigrateToOlderVersion { .. }
+        ));
+
+        // write v0.1 storage: config without admin and a bond status
+        cw2::set_contract_version(deps.as_mut().storage, "deposit-handler", "0.1.0").unwrap();
+        deps.as_mut().storage.set(
+            b"config",
+            br#"{"lock_period_denom_1":1000,"lock_period_denom_2":1000,"denom_1":"qusd","router_denom_1":"router_qusd","denom_2":"uatom","router_denom_2":"router_uatom"}"#,
+        );
+        let mut key = vec![0u8, 19];
+        key.extend_from_slice(b"bond_status_tracker");
+        key.extend_from_slice(b"test_id");
+        deps.as_mut().storage.set(
+            &key,
+            br#"{"sent_to_bond":{"denom_1":"50","denom_2":"0"},"bonded":{"denom_1":"100","denom_2":"100"},"unconfirmed_unbonding":{"denom_1":"0","denom_2":"0"},"unbonding":[],"sent_for_unbond":{"denom_1":"0","denom_2":"0"}}"#,
+        );
+        let mut key = vec![0u8, 21];
+        key.extend_from_slice(b"id_to_address_tracker");
+        key.extend_from_slice(b"test_id");
+        deps.as_mut().storage.set(&key, br#""user""#);
+
+        // an admin is required since the old config has none
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { admin: None }).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::MigrationAdminRequired {
+                stored_version: "0.1.0".into()
+            }
+        );
+
+        migrate(
+            deps.as_mut(),
+            env.clone(),
+            MigrateMsg {
+                admin: Some(ADMIN.into()),
+            },
+        )
+        .unwrap();
+
+        let res: GetConfigResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetConfig {}).unwrap())
+                .unwrap();
+        assert_eq!(
+            res.config,
+            Config {
+                guardian: None,
+                ..get_test_config()
+            }
+        );
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(100));
+        assert_eq!(bond_status.bonded.get(DENOM_2), Uint128::new(100));
+
+        // totals are computed from the migrated positions
+        let res: GetTotalsResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetTotals {}).unwrap())
+                .unwrap();
+        assert_eq!(res.totals.bonded.get(DENOM_1), Uint128::new(100));
+        assert_eq!(res.totals.bonded.get(DENOM_2), Uint128::new(100));
+
+        // the owner index is built
+        let msg = QueryMsg::GetIdsByOwner {
+            owner: USER.into(),
+            start_after: None,
+            limit: None,
+        };
+        let res: GetIdsByOwnerResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.bond_statuses.len(), 1);
+        assert_eq!(res.bond_statuses[0].id, "test_id");
+
+        let version = cw2::get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        // the bond v0.1 was waiting on is recorded, and settled by the router
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(50),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        for expected in [Ok(()), Err(ContractError::NoPendingOperation {})] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(ROUTER_DENOM_1, &[]),
+                ExecuteMsg::Callback(callback.clone().into()),
+            );
+            assert_eq!(res.map(|_| ()), expected);
+        }
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1), Uint128::zero());
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(150));
+    }
+
+    #[test]
+    /// Test pausing and unpausing operations
+    fn pause() {
+        let (mut deps, env) = setup(get_test_config());
+
+        // users cannot pause
+        let msg = ExecuteMsg::SetPauseState {
+            bonding: Some(true),
+            unbonding: None,
+            callbacks: Some(true),
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            msg.clone(),
+        );
+        assert_eq!(res.unwrap_err(), ContractError::Unauthorized {});
+
+        // the guardian can pause
+        execute(deps.as_mut(), env.clone(), mock_info(GUARDIAN, &[]), msg).unwrap();
+        let res: GetPauseStateResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetPauseState {}).unwrap())
+                .unwrap();
+        assert!(res.pause_state.bonding && !res.pause_state.unbonding && res.pause_state.callbacks);
+
+        let bond_msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            msg_info.clone(),
+            bond_msg.clone(),
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            ContractError::Paused {
+                operation: "bonding".into()
+            }
+        );
+
+        let callback = ExecuteMsg::Callback(
+            Callback::BondResponse(BondResponse {
+                share_amount: Uint128::from(10_000u128),
+                bond_id: "test_id".to_string(),
+                nonce: None,
+            })
+            .into(),
         );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            callback,
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            ContractError::Paused {
+                operation: "callbacks".into()
+            }
+        );
+
+        // but the guardian cannot unpause
+        let msg = ExecuteMsg::SetPauseState {
+            bonding: Some(false),
+            unbonding: None,
+            callbacks: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(GUARDIAN, &[]),
+            msg.clone(),
+        );
+        assert_eq!(res.unwrap_err(), ContractError::Unauthorized {});
+
+        // the admin can, and bonding works again
+        execute(deps.as_mut(), env.clone(), mock_info(ADMIN, &[]), msg).unwrap();
+        execute(deps.as_mut(), env, msg_info, bond_msg).unwrap();
+    }
+
+    #[test]
+    /// Test bonding into three strategies at once
+    fn three_assets() {
+        let mut config = get_test_config();
+        config.assets.push(AssetConfig {
+            denom: "uosmo".into(),
+            router: Addr::unchecked("router_uosmo"),
+            lock_period: 500,
+            ratio: Decimal::one(),
+        });
+        let (mut deps, env) = setup(config);
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+
+        // all three denoms are required
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::MismatchAmountDenoms {
+                req_amount_denoms: 3
+            }
+        );
+
+        // one message per router
+        let msg_info = mock_info(
+            USER,
+            &[
+                coin(10_000, DENOM_1),
+                coin(10_000, DENOM_2),
+                coin(10_000, "uosmo"),
+            ],
+        );
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+
+        // the third router confirms its part
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::from(10_000u128),
+            bond_id: "test_id".to_string(),
+            nonce: None,
+        });
+        let msg_info = mock_info("router_uosmo", &[]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            msg_info,
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get("uosmo"), Uint128::new(10_000));
+        assert_eq!(bond_status.sent_to_bond.get("uosmo"), Uint128::zero());
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1), Uint128::new(10_000));
+    }
+
+    #[test]
+    /// Test deposits following a static 10:1 ratio with a tolerance, then unbonding per denom
+    fn static_ratio() {
+        let mut config = get_test_config();
+        config.assets[0].ratio = Decimal::from_ratio(10u128, 1u128);
+        config.ratio_tolerance = Decimal::percent(1);
+        let (mut deps, env) = setup(config);
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+
+        // outside of the tolerance
+        let msg_info = mock_info(USER, &[coin(9_000_000, DENOM_1), coin(1_000_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::FundsAmountNotEqual {});
+
+        // within the tolerance
+        let msg_info = mock_info(USER, &[coin(10_050_000, DENOM_1), coin(1_000_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(
+            bond_status.sent_to_bond.get(DENOM_1),
+            Uint128::new(10_050_000)
+        );
+        assert_eq!(
+            bond_status.sent_to_bond.get(DENOM_2),
+            Uint128::new(1_000_000)
+        );
+
+        // confirm bonds, then start unbonding everything with amounts per denom
+        for (router, amount) in [
+            (ROUTER_DENOM_1, 10_050_000u128),
+            (ROUTER_DENOM_2, 1_000_000),
+        ] {
+            let callback = Callback::BondResponse(BondResponse {
+                share_amount: Uint128::new(amount),
+                bond_id: "test_id".to_string(),
+                nonce: None,
+            });
+            let msg_info = mock_info(router, &[]);
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                msg_info,
+                ExecuteMsg::Callback(callback.into()),
+            )
+            .unwrap();
+        }
+
+        // share_amount and share_amounts cannot be mixed
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000_000)),
+            share_amounts: Some(vec![coin(1_000_000, DENOM_2)]),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap_err();
+        assert_eq!(res, ContractError::InvalidShareAmounts {});
+
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: Some(vec![coin(10_050_000, DENOM_1), coin(1_000_000, DENOM_2)]),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert!(bond_status.bonded.is_zero());
+        assert_eq!(
+            bond_status.unconfirmed_unbonding.get(DENOM_1),
+            Uint128::new(10_050_000)
+        );
+        assert_eq!(
+            bond_status.unconfirmed_unbonding.get(DENOM_2),
+            Uint128::new(1_000_000)
+        );
+        // amounts of 18 decimals denoms are far above what a Decimal holds
+        let msg = ExecuteMsg::Bond {
+            id: Some("large_id".into()),
+        };
+        let msg_info = mock_info(
+            USER,
+            &[
+                coin(10_000 * 10u128.pow(24), DENOM_1),
+                coin(1_000 * 10u128.pow(24), DENOM_2),
+            ],
+        );
+        execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap();
+        let msg_info = mock_info(
+            USER,
+            &[
+                coin(9_000 * 10u128.pow(24), DENOM_1),
+                coin(1_000 * 10u128.pow(24), DENOM_2),
+            ],
+        );
+        let res = execute(deps.as_mut(), env, msg_info, msg).unwrap_err();
+        assert_eq!(res, ContractError::FundsAmountNotEqual {});
+    }
+
+    #[test]
+    /// Test deposits of equal value using prices from an oracle
+    fn oracle_ratio() {
+        let mut config = get_test_config();
+        config.ratio_source = RatioSource::Oracle {
+            contract: Addr::unchecked("oracle"),
+        };
+        let (mut deps, env) = setup(config);
+
+        // qusd is worth 1, uatom is worth 10
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } => {
+                let price = match from_binary(msg).unwrap() {
+                    ExternalQueryMsg::Price { denom } if denom == DENOM_1 => Decimal::one(),
+                    ExternalQueryMsg::Price { .. } => Decimal::from_ratio(10u128, 1u128),
+                    _ => panic!("unexpected query"),
+                };
+                SystemResult::Ok(ContractResult::Ok(
+                    to_binary(&PriceResponse { price }).unwrap(),
+                ))
+            }
+            _ => panic!("unexpected query"),
+        });
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::FundsAmountNotEqual {});
+
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(1_000, DENOM_2)]);
+        execute(deps.as_mut(), env, msg_info, msg).unwrap();
+    }
+
+    #[test]
+    /// Test bonding the balanced portion of a deposit and refunding the excess
+    fn refund_excess() {
+        let mut config = get_test_config();
+        config.refund_excess = true;
+        let (mut deps, env) = setup(config);
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(7_500, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        // two router messages and the refund
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(2_500, DENOM_1)],
+            })
+        );
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1), Uint128::new(7_500));
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_2), Uint128::new(7_500));
+
+        // large amounts of 18 decimals denoms are balanced the same way, as are the single sided
+        // deposits once swapped
+        let msg = ExecuteMsg::Bond {
+            id: Some("large_id".into()),
+        };
+        let msg_info = mock_info(
+            USER,
+            &[
+                coin(10_000 * 10u128.pow(24), DENOM_1),
+                coin(7_500 * 10u128.pow(24), DENOM_2),
+            ],
+        );
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(2_500 * 10u128.pow(24), DENOM_1)],
+            })
+        );
+
+        // a balanced deposit has nothing to refund
+        let msg = ExecuteMsg::Bond {
+            id: Some("balanced_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert!(res.events[0]
+            .attributes
+            .iter()
+            .all(|attribute| attribute.key != "refund"));
+    }
+
+    #[test]
+    /// Test listing positions page by page
+    fn list_bond_statuses() {
+        let (mut deps, env) = setup(get_test_config());
+
+        for (owner, id) in [(USER, "id_c"), (USER, "id_a"), ("other_user", "id_b")] {
+            let msg = ExecuteMsg::Bond {
+                id: Some(id.into()),
+            };
+            let msg_info = mock_info(owner, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+            execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        }
+
+        let list = |start_after: Option<&str>, limit: Option<u32>| -> ListBondStatusesResponse {
+            let msg = QueryMsg::ListBondStatuses {
+                start_after: start_after.map(|id| id.to_owned()),
+                limit,
+            };
+            return from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        };
+
+        let res = list(None, Some(2));
+        let ids: Vec<&str> = res.bond_statuses.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["id_a", "id_b"]);
+        assert_eq!(res.bond_statuses[0].owner, Addr::unchecked(USER));
+        assert_eq!(res.bond_statuses[1].owner, Addr::unchecked("other_user"));
+        assert_eq!(
+            res.bond_statuses[0].bond_status.sent_to_bond.get(DENOM_1),
+            Uint128::new(10_000)
+        );
+
+        let res = list(Some("id_b"), Some(2));
+        let ids: Vec<&str> = res.bond_statuses.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["id_c"]);
+
+        // the limit is capped
+        let res = list(None, Some(1_000));
+        assert_eq!(res.bond_statuses.len(), 3);
+    }
+
+    #[test]
+    /// Test listing the ids of an owner
+    fn ids_by_owner() {
+        let (mut deps, env) = setup(get_test_config());
+
+        for (owner, id) in [(USER, "id_c"), (USER, "id_a"), ("other_user", "id_b")] {
+            let msg = ExecuteMsg::Bond {
+                id: Some(id.into()),
+            };
+            let msg_info = mock_info(owner, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+            execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        }
+
+        let ids_by_owner = |owner: &str, start_after: Option<&str>| -> Vec<String> {
+            let msg = QueryMsg::GetIdsByOwner {
+                owner: owner.into(),
+                start_after: start_after.map(|id| id.to_owned()),
+                limit: None,
+            };
+            let res: GetIdsByOwnerResponse =
+                from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            return res.bond_statuses.into_iter().map(|e| e.id).collect();
+        };
+
+        assert_eq!(ids_by_owner(USER, None), vec!["id_a", "id_c"]);
+        assert_eq!(ids_by_owner(USER, Some("id_a")), vec!["id_c"]);
+        assert_eq!(ids_by_owner("other_user", None), vec!["id_b"]);
+        assert!(ids_by_owner("nobody", None).is_empty());
+
+        // a malformed owner is an error, not a panic
+        let msg = QueryMsg::GetIdsByOwner {
+            owner: "Not_Normalized".into(),
+            start_after: None,
+            limit: None,
+        };
+        query(deps.as_ref(), env, msg).unwrap_err();
+    }
+
+    #[test]
+    /// Test the totals of all positions follow every operation
+    fn totals() {
+        let (mut deps, env) = setup(get_test_config());
+
+        let get_totals = |deps: &Deps| -> GetTotalsResponse {
+            return from_binary(&query(*deps, env.clone(), QueryMsg::GetTotals {}).unwrap())
+                .unwrap();
+        };
+        assert_eq!(get_totals(&deps.as_ref()).totals, Default::default());
+
+        for (owner, id) in [(USER, "id_a"), ("other_user", "id_b")] {
+            let msg = ExecuteMsg::Bond {
+                id: Some(id.into()),
+            };
+            let msg_info = mock_info(owner, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+            execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        }
+        let totals = get_totals(&deps.as_ref()).totals;
+        assert_eq!(totals.sent_to_bond.get(DENOM_1), Uint128::new(20_000));
+        assert_eq!(totals.sent_to_bond.get(DENOM_2), Uint128::new(20_000));
+
+        // the first router confirms id_a
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "id_a".into(),
+            nonce: None,
+        });
+        let msg_info = mock_info(ROUTER_DENOM_1, &[]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            msg_info,
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        let totals = get_totals(&deps.as_ref()).totals;
+        assert_eq!(totals.sent_to_bond.get(DENOM_1), Uint128::new(10_000));
+        assert_eq!(totals.bonded.get(DENOM_1), Uint128::new(10_000));
+
+        // start unbonding part of it
+        let msg = ExecuteMsg::StartUnbond {
+            id: "id_a".into(),
+            share_amount: None,
+            share_amounts: Some(vec![coin(4_000, DENOM_1), coin(0, DENOM_2)]),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let totals = get_totals(&deps.as_ref()).totals;
+        assert_eq!(totals.bonded.get(DENOM_1), Uint128::new(6_000));
+        assert_eq!(
+            totals.unconfirmed_unbonding.get(DENOM_1),
+            Uint128::new(4_000)
+        );
+
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "id_a".into(),
+            nonce: None,
+        });
+        let msg_info = mock_info(ROUTER_DENOM_1, &[]);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            msg_info,
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        let totals = get_totals(&deps.as_ref()).totals;
+        assert_eq!(totals.unconfirmed_unbonding.get(DENOM_1), Uint128::zero());
+        assert_eq!(totals.unbonding.get(DENOM_1), Uint128::new(4_000));
+        assert_eq!(totals.sent_to_bond.get(DENOM_2), Uint128::new(20_000));
+    }
+
+    #[test]
+    /// Test moving a position to another owner
+    fn transfer_id() {
+        let (mut deps, env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        let msg = ExecuteMsg::TransferId {
+            id: "test_id".into(),
+            new_owner: "new_owner".into(),
+        };
+
+        // only the owner can transfer
+        let msg_info = mock_info("new_owner", &[]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        // unknown id
+        let msg_info = mock_info(USER, &[]);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            msg_info.clone(),
+            ExecuteMsg::TransferId {
+                id: "unknown_id".into(),
+                new_owner: "new_owner".into(),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::IdNotAllocated {});
+
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        let ids_by_owner = |owner: &str| -> Vec<String> {
+            let msg = QueryMsg::GetIdsByOwner {
+                owner: owner.into(),
+                start_after: None,
+                limit: None,
+            };
+            let res: GetIdsByOwnerResponse =
+                from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            return res.bond_statuses.into_iter().map(|e| e.id).collect();
+        };
+        assert!(ids_by_owner(USER).is_empty());
+        assert_eq!(ids_by_owner("new_owner"), vec!["test_id"]);
+
+        // the balances moved with the id
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1), Uint128::new(10_000));
+
+        // the previous owner cannot use the id anymore, the new one can
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let funds = [coin(10_000, DENOM_1), coin(10_000, DENOM_2)];
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("new_owner", &funds),
+            msg,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    /// Test operators unbonding on behalf of the owner
+    fn approvals() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        for id in ["id_a", "id_b"] {
+            let msg = ExecuteMsg::Bond {
+                id: Some(id.into()),
+            };
+            let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+            execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+            let callback = Callback::BondResponse(BondResponse {
+                share_amount: Uint128::new(10_000),
+                bond_id: id.into(),
+                nonce: None,
+            });
+            for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+                let msg = ExecuteMsg::Callback(callback.clone().into());
+                execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+            }
+        }
+
+        let start_unbond = |id: &str| ExecuteMsg::StartUnbond {
+            id: id.into(),
+            share_amount: Some(Uint128::new(100)),
+            share_amounts: None,
+        };
+        let keeper = mock_info("keeper", &[]);
+
+        // not approved yet
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            keeper.clone(),
+            start_unbond("id_a"),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        // only the owner can approve, and not with an expired approval
+        let msg = ExecuteMsg::Approve {
+            id: "id_a".into(),
+            operator: "keeper".into(),
+            expires: Some(Expiration::AtHeight(env.block.height + 10)),
+            allow_custom_recipient: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), msg.clone()).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let expired = ExecuteMsg::Approve {
+            id: "id_a".into(),
+            operator: "keeper".into(),
+            expires: Some(Expiration::AtHeight(env.block.height)),
+            allow_custom_recipient: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), expired).unwrap_err();
+        assert_eq!(res, ContractError::ApprovalExpired {});
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        let res: GetApprovalsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::GetApprovals { id: "id_a".into() },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.approvals.len(), 1);
+        assert_eq!(res.approvals[0].operator, Addr::unchecked("keeper"));
+
+        // the approval only covers id_a
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            keeper.clone(),
+            start_unbond("id_a"),
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            keeper.clone(),
+            start_unbond("id_b"),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        // approving the keeper for every id of the owner
+        let msg = ExecuteMsg::ApproveAll {
+            operator: "keeper".into(),
+            expires: None,
+            allow_custom_recipient: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            keeper.clone(),
+            start_unbond("id_b"),
+        )
+        .unwrap();
+
+        let res: GetOperatorsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::GetOperators { owner: USER.into() },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.operators.len(), 1);
+        assert_eq!(res.operators[0].operator, Addr::unchecked("keeper"));
+        let msg = QueryMsg::GetOperators {
+            owner: "Not_Normalized".into(),
+        };
+        query(deps.as_ref(), env.clone(), msg).unwrap_err();
+
+        // the operator can unbond, funds still go to the owner
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "id_a".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(60 * 60 * 24);
+        let msg = ExecuteMsg::Unbond {
+            id: "id_a".into(),
+            share_amount: Some(Uint128::new(100)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), keeper.clone(), msg).unwrap();
+        let callback = Callback::UnbondResponse(UnbondResponse {
+            unbond_id: "id_a".into(),
+            share_amount: None,
+            nonce: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(100, DENOM_1)]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(100, DENOM_1)]
+            })
+        );
+
+        // revoking both approvals
+        let msg = ExecuteMsg::RevokeAll {
+            operator: "keeper".into(),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let msg = ExecuteMsg::Revoke {
+            id: "id_a".into(),
+            operator: "keeper".into(),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let msg = ExecuteMsg::StartUnbond {
+            id: "id_a".into(),
+            share_amount: Some(Uint128::new(100)),
+            share_amounts: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), keeper, msg).unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+    }
+
+    #[test]
+    /// Test unbonded funds sent to a custom recipient, executing a message on it
+    fn unbond_recipient() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(60 * 60 * 24);
+
+        let forward_msg = Binary::from(br#"{"deposit":{}}"#);
+        let unbond = |amount: u128, recipient: Option<&str>| ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(amount)),
+            share_amounts: None,
+            recipient: recipient.map(|recipient| recipient.to_owned()),
+            msg: recipient.map(|_| forward_msg.clone()),
+        };
+
+        // an operator needs to be allowed to pick the recipient
+        let msg = ExecuteMsg::ApproveAll {
+            operator: "keeper".into(),
+            expires: None,
+            allow_custom_recipient: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("keeper", &[]),
+            unbond(300, Some("treasury")),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        let msg = ExecuteMsg::ApproveAll {
+            operator: "keeper".into(),
+            expires: None,
+            allow_custom_recipient: Some(true),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let treasury_unbond = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("keeper", &[]),
+            unbond(300, Some("treasury")),
+        )
+        .unwrap();
+
+        // a second unbonding to the owner
+        let owner_unbond = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            unbond(200, None),
+        )
+        .unwrap();
+
+        // without a nonce the oldest unbonding is settled, it goes to the treasury
+        let callback = ExecuteMsg::Callback(
+            Callback::UnbondResponse(UnbondResponse {
+                unbond_id: "test_id".into(),
+                share_amount: None,
+                nonce: None,
+            })
+            .into(),
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(300, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "treasury".into(),
+                msg: forward_msg.clone(),
+                funds: vec![coin(300, DENOM_1)],
+            })
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(200, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(200, DENOM_1)]
+            })
+        );
+
+        // each answer goes to the destination of its own unbonding, whatever the order
+        let callback = |nonce: u64| {
+            ExecuteMsg::Callback(
+                Callback::UnbondResponse(UnbondResponse {
+                    unbond_id: "test_id".into(),
+                    share_amount: None,
+                    nonce: Some(nonce),
+                })
+                .into(),
+            )
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(200, DENOM_2)]),
+            callback(owner_unbond.messages[1].id),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(200, DENOM_2)]
+            })
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(300, DENOM_2)]),
+            callback(treasury_unbond.messages[1].id),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "treasury".into(),
+                msg: forward_msg,
+                funds: vec![coin(300, DENOM_2)],
+            })
+        );
+    }
+
+    #[test]
+    /// Test ids generated for bonds without an id
+    fn generated_ids() {
+        let (mut deps, env) = setup(get_test_config());
+        let funds = [coin(10_000, DENOM_1), coin(10_000, DENOM_2)];
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            ExecuteMsg::Bond { id: None },
+        )
+        .unwrap();
+        let data: BondData = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.id, "1");
+        assert!(res.events[0]
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "id" && attr.value == "1"));
+
+        // user supplied ids still work, and the data returns them too
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("other_user", &funds),
+            ExecuteMsg::Bond {
+                id: Some("2".into()),
+            },
+        )
+        .unwrap();
+        let data: BondData = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.id, "2");
+
+        // ids already in use are skipped
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            ExecuteMsg::Bond { id: None },
+        )
+        .unwrap();
+        let data: BondData = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(data.id, "3");
+
+        let msg = QueryMsg::GetIdsByOwner {
+            owner: USER.into(),
+            start_after: None,
+            limit: None,
+        };
+        let res: GetIdsByOwnerResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        let ids: Vec<String> = res.bond_statuses.into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["1", "3"]);
+    }
+
+    #[test]
+    /// Test the rules applied to user supplied ids
+    fn id_validation() {
+        let (mut deps, env) = setup(get_test_config());
+        let funds = [coin(10_000, DENOM_1), coin(10_000, DENOM_2)];
+        let bond = |id: &str| ExecuteMsg::Bond {
+            id: Some(id.into()),
+        };
+
+        // an id allocated before the rules change stays usable
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond("sys:legacy"),
+        )
+        .unwrap();
+
+        // default rules
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond(""),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidId {
+                reason: "length must be between 1 and 64".into()
+            }
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond(&"a".repeat(65)),
+        )
+        .unwrap_err();
+        assert!(matches!(res, ContractError::InvalidId { .. }));
+
+        // the minimum length cannot exceed the maximum
+        let update_config = |id_validation: IdValidation| ExecuteMsg::UpdateConfig {
+            assets: vec![],
+            ratio_source: None,
+            ratio_tolerance: None,
+            refund_excess: None,
+            swap_contract: None,
+            id_validation: Some(id_validation),
+            bond_timeout: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ADMIN, &[]),
+            update_config(IdValidation {
+                min_length: 10,
+                max_length: 5,
+                ..IdValidation::default()
+            }),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::InvalidIdValidation {});
+
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ADMIN, &[]),
+            update_config(IdValidation {
+                min_length: 3,
+                max_length: 16,
+                allowed_chars: Some("abcdefghijklmnopqrstuvwxyz0123456789_:".into()),
+                reserved_prefixes: vec!["sys:".into()],
+            }),
+        )
+        .unwrap();
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond("Test_id"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidId {
+                reason: "character 'T' is not allowed".into()
+            }
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond("sys:new"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidId {
+                reason: "prefix sys: is reserved".into()
+            }
+        );
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond("test_id"),
+        )
+        .unwrap();
+
+        // the rules also apply when unbonding and in callbacks
+        let msg = ExecuteMsg::StartUnbond {
+            id: "x".into(),
+            share_amount: Some(Uint128::new(1)),
+            share_amounts: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap_err();
+        assert!(matches!(res, ContractError::InvalidId { .. }));
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "x".into(),
+            nonce: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap_err();
+        assert!(matches!(res, ContractError::InvalidId { .. }));
+
+        // existing ids are still accepted
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &funds),
+            bond("sys:legacy"),
+        )
+        .unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "sys:legacy".into(),
+            nonce: None,
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    /// Test positions are closed once everything has been withdrawn
+    fn close_position() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(1_000, DENOM_1), coin(1_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(1_000),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        // only the owner can close, and only an empty position
+        let msg = ExecuteMsg::CloseId {
+            id: "test_id".into(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("other_user", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap_err();
+        assert_eq!(res, ContractError::PositionNotEmpty {});
+
+        // withdraw everything
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(60 * 60 * 24);
+        let msg = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        let callback = ExecuteMsg::Callback(
+            Callback::UnbondResponse(UnbondResponse {
+                unbond_id: "test_id".into(),
+                share_amount: None,
+                nonce: None,
+            })
+            .into(),
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(1_000, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap();
+        assert!(!res.events.iter().any(|e| e.ty == "position_closed"));
+
+        // the last funds close the position
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(1_000, DENOM_2)]),
+            callback,
+        )
+        .unwrap();
+        assert!(res.events.iter().any(|e| e.ty == "position_closed"));
+
+        let res: GetBondStatusResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::GetBondStatus {
+                    id: "test_id".into(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(res.bond_status, None);
+
+        // the id is free again
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info("other_user", &[coin(1_000, DENOM_1), coin(1_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+    }
+
+    #[test]
+    /// Test operations rejected by a router are rolled back
+    fn router_replies() {
+        let (mut deps, env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        // every router message waits for a reply, with its own reply id
+        assert_eq!(res.messages.len(), 2);
+        assert!(res
+            .messages
+            .iter()
+            .all(|msg| msg.reply_on == ReplyOn::Always));
+        assert_eq!(res.messages[0].id, 1);
+        assert_eq!(res.messages[1].id, 2);
+
+        let accepted = |id: u64| Reply {
+            id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        let rejected = |id: u64| Reply {
+            id,
+            result: SubMsgResult::Err("router error".into()),
+        };
+
+        // the first router accepts, the second one fails: the whole deposit fails, so the
+        // first router does not keep a one-sided position
+        reply(deps.as_mut(), env.clone(), accepted(1)).unwrap();
+        let res = reply(deps.as_mut(), env.clone(), rejected(2)).unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::BondRejected {
+                denom: DENOM_2.into(),
+                error: "router error".into()
+            }
+        );
+
+        // the same deposit goes through once both routers accept it
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let (mut deps, env) = setup(get_test_config());
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        for id in [1, 2] {
+            let res = reply(deps.as_mut(), env.clone(), accepted(id)).unwrap();
+            let event = res.events.iter().find(|e| e.ty == "router_reply").unwrap();
+            assert!(event
+                .attributes
+                .iter()
+                .any(|attr| attr.key == "accepted" && attr.value == "true"));
+        }
+
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        // a rejected start of unbonding goes back to bonded
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: Some(vec![coin(4_000, DENOM_1), coin(0, DENOM_2)]),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, 3);
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(6_000));
+
+        let res = reply(deps.as_mut(), env.clone(), rejected(3)).unwrap();
+        let event = res.events.iter().find(|e| e.ty == "router_reply").unwrap();
+        assert!(event
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "error" && attr.value == "router error"));
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(10_000));
+        assert_eq!(
+            bond_status.unconfirmed_unbonding.get(DENOM_1),
+            Uint128::zero()
+        );
+
+        let res: GetTotalsResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetTotals {}).unwrap())
+                .unwrap();
+        assert_eq!(res.totals.bonded.get(DENOM_1), Uint128::new(10_000));
+        assert_eq!(res.totals.bonded.get(DENOM_2), Uint128::new(10_000));
+    }
+
+    #[test]
+    /// Test reclaiming bonds a router never confirmed
+    fn reclaim_stale_bond() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        // the first router confirms, the second one never does
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+
+        // bonds can only be reclaimed after the timeout, and by the owner or its operators
+        let reclaim = ExecuteMsg::ReclaimStaleBond {
+            id: "test_id".into(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            reclaim.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NoStaleBond {});
+
+        env.block.time = env.block.time.plus_seconds(DEFAULT_BOND_TIMEOUT);
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ADMIN, &[]),
+            reclaim.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::Unauthorized {});
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            reclaim.clone(),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: ROUTER_DENOM_2.into(),
+                msg: to_binary(&ExternalExecuteMsg::ReturnBond {
+                    id: "test_id".into(),
+                    amount: Uint128::new(10_000),
+                    nonce: 2,
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+
+        // the same bond is only reclaimed once
+        let res = execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), reclaim).unwrap_err();
+        assert_eq!(res, ContractError::NoStaleBond {});
+
+        // returned funds must be the denom of the router
+        let callback = ExecuteMsg::Callback(
+            Callback::ReturnBondResponse(ReturnBondResponse {
+                bond_id: "test_id".into(),
+                nonce: None,
+            })
+            .into(),
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(10_000, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidDenom {
+                denom: DENOM_2.into()
+            }
+        );
+
+        // and exactly the amount of the bond, neither more nor less
+        for amount in [20_000, 5_000] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(ROUTER_DENOM_2, &[coin(amount, DENOM_2)]),
+                callback.clone(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                res,
+                ContractError::InvalidReturnedAmount {
+                    amount: coin(amount, DENOM_2),
+                    expected: coin(10_000, DENOM_2),
+                }
+            );
+        }
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_2), Uint128::new(10_000));
+
+        // the returned funds go to the owner
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(10_000, DENOM_2)]),
+            callback,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(10_000, DENOM_2)]
+            })
+        );
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_2), Uint128::zero());
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(10_000));
+    }
+
+    #[test]
+    /// Test tagged callbacks, and the untagged callbacks of routers written for v0.1
+    fn callback_formats() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        let tagged: ExecuteMsg = from_slice(
+            br#"{"callback":{"bond_response":{"share_amount":"10000","bond_id":"test_id"}}}"#,
+        )
+        .unwrap();
+        let legacy: ExecuteMsg =
+            from_slice(br#"{"callback":{"share_amount":"10000","bond_id":"test_id"}}"#).unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            tagged,
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[]),
+            legacy,
+        )
+        .unwrap();
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(10_000));
+        assert_eq!(bond_status.bonded.get(DENOM_2), Uint128::new(10_000));
+
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        // untagged unbond callbacks without funds start the unbonding
+        let tagged: ExecuteMsg =
+            from_slice(br#"{"callback":{"start_unbond_response":{"unbond_id":"test_id"}}}"#)
+                .unwrap();
+        let legacy: ExecuteMsg = from_slice(br#"{"callback":{"unbond_id":"test_id"}}"#).unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            tagged,
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[]),
+            legacy.clone(),
+        )
+        .unwrap();
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.unbonding.len(), 2);
+        assert_eq!(
+            bond_status.unconfirmed_unbonding.get(DENOM_2),
+            Uint128::zero()
+        );
+
+        env.block.time = env.block.time.plus_seconds(LOCK_PERIOD_DENOM_1 + 1);
+        let msg = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        // and complete it when funds are attached
+        let tagged: ExecuteMsg =
+            from_slice(br#"{"callback":{"unbond_response":{"unbond_id":"test_id"}}}"#).unwrap();
+        for (router, msg, denom) in [
+            (ROUTER_DENOM_1, tagged, DENOM_1),
+            (ROUTER_DENOM_2, legacy, DENOM_2),
+        ] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(router, &[coin(1_000, denom)]),
+                msg,
+            )
+            .unwrap();
+            assert_eq!(
+                res.messages[0].msg,
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: USER.into(),
+                    amount: vec![coin(1_000, denom)]
+                })
+            );
+        }
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_1), Uint128::zero());
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_2), Uint128::zero());
+    }
+
+    #[test]
+    /// Test callbacks are matched to the operation sent to the router through their nonce
+    fn callback_nonces() {
+        let (mut deps, env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        let res = execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+
+        // every router gets the nonce of its operation
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: ROUTER_DENOM_2.into(),
+                msg: to_binary(&ExternalExecuteMsg::OnBond {
+                    id: "test_id".into(),
+                    nonce: 2,
+                })
+                .unwrap(),
+                funds: vec![coin(10_000, DENOM_2)],
+            })
+        );
+
+        let bond_response = |nonce: u64| {
+            ExecuteMsg::Callback(
+                Callback::BondResponse(BondResponse {
+                    share_amount: Uint128::new(10_000),
+                    bond_id: "test_id".into(),
+                    nonce: Some(nonce),
+                })
+                .into(),
+            )
+        };
+
+        // nonces sent to another router, or never sent, are rejected
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            bond_response(2),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::UnknownOperation { nonce: 2 });
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            bond_response(99),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::UnknownOperation { nonce: 99 });
+
+        // each operation is only settled once
+        for (router, nonce) in [(ROUTER_DENOM_1, 1), (ROUTER_DENOM_2, 2)] {
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(router, &[]),
+                bond_response(nonce),
+            )
+            .unwrap();
+        }
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            bond_response(1),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::OperationAlreadySettled { nonce: 1 });
+
+        // replays without a nonce are rejected once nothing is waiting on the router
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: Some(Uint128::new(1_000)),
+            share_amounts: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let callback = ExecuteMsg::Callback(
+            Callback::StartUnbondResponse(StartUnbondResponse {
+                unbond_id: "test_id".into(),
+                nonce: None,
+            })
+            .into(),
+        );
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            callback.clone(),
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            callback,
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NoPendingOperation {});
+
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.unbonding.len(), 1);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(9_000));
+    }
+
+    #[test]
+    /// Test listing the operations waiting on a router
+    fn list_pending_operations() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let funds = [coin(10_000, DENOM_1), coin(10_000, DENOM_2)];
+        for id in ["id_a", "id_b"] {
+            let msg = ExecuteMsg::Bond {
+                id: Some(id.into()),
+            };
+            execute(deps.as_mut(), env.clone(), mock_info(USER, &funds), msg).unwrap();
+            env.block.time = env.block.time.plus_seconds(100);
+        }
+
+        // the first router confirms the bond of id_a
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "id_a".into(),
+            nonce: Some(1),
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+
+        let list = |id: Option<&str>,
+                    router: Option<&str>,
+                    older_than: Option<u64>,
+                    start_after: Option<u64>,
+                    limit: Option<u32>| {
+            let msg = QueryMsg::ListPendingOperations {
+                id: id.map(|id| id.to_owned()),
+                router: router.map(|router| router.to_owned()),
+                older_than,
+                start_after,
+                limit,
+            };
+            let res: ListPendingOperationsResponse =
+                from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            return res
+                .pending_operations
+                .iter()
+                .map(|operation| operation.nonce)
+                .collect::<Vec<u64>>();
+        };
+
+        assert_eq!(list(None, None, None, None, None), vec![2, 3, 4]);
+        assert_eq!(list(Some("id_a"), None, None, None, None), vec![2]);
+        assert_eq!(list(None, Some(ROUTER_DENOM_1), None, None, None), vec![3]);
+        assert_eq!(
+            list(Some("id_b"), Some(ROUTER_DENOM_2), None, None, None),
+            vec![4]
+        );
+        assert_eq!(list(None, None, Some(150), None, None), vec![2]);
+        assert_eq!(list(None, None, None, Some(2), Some(1)), vec![3]);
+
+        // filtered lists are paged by nonce as well
+        assert_eq!(list(Some("id_b"), None, None, None, None), vec![3, 4]);
+        assert_eq!(list(Some("id_b"), None, None, Some(3), None), vec![4]);
+        assert_eq!(list(Some("id_b"), None, None, None, Some(1)), vec![3]);
+        assert_eq!(
+            list(None, Some(ROUTER_DENOM_2), None, None, None),
+            vec![2, 4]
+        );
+        assert_eq!(
+            list(None, Some(ROUTER_DENOM_2), None, Some(2), None),
+            vec![4]
+        );
+        assert_eq!(
+            list(None, Some(ROUTER_DENOM_2), None, None, Some(1)),
+            vec![2]
+        );
+
+        // a malformed router is an error, not a panic
+        let msg = QueryMsg::ListPendingOperations {
+            id: None,
+            router: Some("Not_Normalized".into()),
+            older_than: None,
+            start_after: None,
+            limit: None,
+        };
+        query(deps.as_ref(), env.clone(), msg).unwrap_err();
+
+        let msg = QueryMsg::ListPendingOperations {
+            id: Some("id_b".into()),
+            router: None,
+            older_than: None,
+            start_after: None,
+            limit: Some(1),
+        };
+        let res: ListPendingOperationsResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        let operation = &res.pending_operations[0];
+        assert_eq!(operation.router, Addr::unchecked(ROUTER_DENOM_1));
+        assert_eq!(operation.kind, OperationKind::Bond);
+        assert_eq!(operation.amount, Uint128::new(10_000));
+        assert_eq!(
+            operation.dispatched_at,
+            mock_env().block.time.plus_seconds(100)
+        );
+    }
+
+    #[test]
+    /// Test routers reporting operations they could not carry out
+    fn failure_callbacks() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "test_id".into(),
+            nonce: Some(1),
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+
+        // the second router could not bond, the funds it returns go to the owner
+        let bond_failed = ExecuteMsg::Callback(
+            Callback::BondFailed(BondFailure {
+                bond_id: "test_id".into(),
+                reason: "strategy is full".into(),
+                nonce: Some(2),
+            })
+            .into(),
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(10_000, DENOM_1)]),
+            bond_failed.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::InvalidDenom {
+                denom: DENOM_1.into()
+            }
+        );
+        for amount in [0, 5_000, 20_000] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(ROUTER_DENOM_2, &coins(amount, DENOM_2)),
+                bond_failed.clone(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                res,
+                ContractError::InvalidReturnedAmount {
+                    amount: coin(amount, DENOM_2),
+                    expected: coin(10_000, DENOM_2),
+                }
+            );
+        }
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_2, &[coin(10_000, DENOM_2)]),
+            bond_failed,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(10_000, DENOM_2)]
+            })
+        );
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_2), Uint128::zero());
+
+        // a failed start of unbonding goes back to bonded
+        let start_unbond = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: Some(vec![coin(4_000, DENOM_1), coin(0, DENOM_2)]),
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            start_unbond.clone(),
+        )
+        .unwrap();
+        let callback = ExecuteMsg::Callback(
+            Callback::StartUnbondFailed(StartUnbondFailure {
+                unbond_id: "test_id".into(),
+                reason: "strategy is paused".into(),
+                nonce: None,
+            })
+            .into(),
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(4_000, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::UnexpectedFunds {});
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            callback,
+        )
+        .unwrap();
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(10_000));
+        assert_eq!(
+            bond_status.unconfirmed_unbonding.get(DENOM_1),
+            Uint128::zero()
+        );
+
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            start_unbond,
+        )
+        .unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        env.block.time = env.block.time.plus_seconds(LOCK_PERIOD_DENOM_1 + 1);
+
+        // a failed unbonding can be unbonded again
+        let unbond = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: Some(vec![coin(4_000, DENOM_1), coin(0, DENOM_2)]),
+            recipient: None,
+            msg: None,
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(USER, &[]),
+            unbond.clone(),
+        )
+        .unwrap();
+        let callback = ExecuteMsg::Callback(
+            Callback::UnbondFailed(UnbondFailure {
+                unbond_id: "test_id".into(),
+                reason: "withdrawal failed".into(),
+                nonce: None,
+            })
+            .into(),
+        );
+
+        // the shares are rolled back, so the router cannot pay them out as well
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(4_000, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::UnexpectedFunds {});
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(
+            bond_status.sent_for_unbond.get(DENOM_1),
+            Uint128::new(4_000)
+        );
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            callback,
+        )
+        .unwrap();
+        assert!(res.messages.is_empty());
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_1), Uint128::zero());
+        assert_eq!(bond_status.unbonding[0].value, Uint128::new(4_000));
+
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), unbond).unwrap();
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(
+            bond_status.sent_for_unbond.get(DENOM_1),
+            Uint128::new(4_000)
+        );
+    }
+
+    #[test]
+    /// Test the funds of unbond responses are checked against what is waiting on the router
+    fn unbond_funds() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::new(10_000),
+            bond_id: "test_id".into(),
+            nonce: None,
+        });
+        for router in [ROUTER_DENOM_1, ROUTER_DENOM_2] {
+            let msg = ExecuteMsg::Callback(callback.clone().into());
+            execute(deps.as_mut(), env.clone(), mock_info(router, &[]), msg).unwrap();
+        }
+
+        let share_amounts = Some(vec![coin(1_000, DENOM_1), coin(0, DENOM_2)]);
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: share_amounts.clone(),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        env.block.time = env.block.time.plus_seconds(LOCK_PERIOD_DENOM_1 + 1);
+        let msg = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        let callback = ExecuteMsg::Callback(
+            Callback::UnbondResponse(UnbondResponse {
+                unbond_id: "test_id".into(),
+                share_amount: None,
+                nonce: None,
+            })
+            .into(),
+        );
+
+        // exactly one coin of the router denom must be attached
+        for funds in [
+            vec![],
+            vec![coin(1_000, DENOM_2)],
+            vec![coin(500, DENOM_1), coin(500, DENOM_2)],
+        ] {
+            let res = execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(ROUTER_DENOM_1, &funds),
+                callback.clone(),
+            )
+            .unwrap_err();
+            assert_eq!(
+                res,
+                ContractError::InvalidUnbondFunds {
+                    denom: DENOM_1.into()
+                }
+            );
+        }
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(1_500, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::UnbondFundsTooHigh {
+                amount: coin(1_500, DENOM_1),
+                expected: coin(1_000, DENOM_1),
+            }
+        );
+
+        // a short payment settles part of the unbonding
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(400, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(400, DENOM_1)]
+            })
+        );
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_1), Uint128::new(600));
+        let msg = QueryMsg::ListPendingOperations {
+            id: Some("test_id".into()),
+            router: None,
+            older_than: None,
+            start_after: None,
+            limit: None,
+        };
+        let res: ListPendingOperationsResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.pending_operations.len(), 1);
+        assert_eq!(res.pending_operations[0].amount, Uint128::new(600));
+
+        // and the rest settles it
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(600, DENOM_1)]),
+            callback.clone(),
+        )
+        .unwrap();
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_1), Uint128::zero());
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(1, DENOM_1)]),
+            callback,
+        )
+        .unwrap_err();
+        assert_eq!(res, ContractError::NoPendingOperation {});
+    }
+
+    #[test]
+    /// Test shares are tracked apart from the deposited tokens, and valued by the routers
+    fn exchange_rates() {
+        let (mut deps, mut env) = setup(get_test_config());
+
+        // shares of the first router are worth 2.2 tokens, the second one is 1:1
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } => {
+                let amount = match from_binary(msg).unwrap() {
+                    ExternalQueryMsg::ConvertToAssets { shares }
+                        if contract_addr == ROUTER_DENOM_1 =>
+                    {
+                        shares * Decimal::from_ratio(22u128, 10u128)
+                    }
+                    ExternalQueryMsg::ConvertToAssets { shares } => shares,
+                    _ => panic!("unexpected query"),
+                };
+                SystemResult::Ok(ContractResult::Ok(
+                    to_binary(&ConvertToAssetsResponse { amount }).unwrap(),
+                ))
+            }
+            _ => panic!("unexpected query"),
+        });
+
+        let msg = ExecuteMsg::Bond {
+            id: Some("test_id".into()),
+        };
+        let msg_info = mock_info(USER, &[coin(10_000, DENOM_1), coin(10_000, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        for (router, shares) in [(ROUTER_DENOM_1, 5_000u128), (ROUTER_DENOM_2, 10_000)] {
+            let callback = Callback::BondResponse(BondResponse {
+                share_amount: Uint128::new(shares),
+                bond_id: "test_id".into(),
+                nonce: None,
+            });
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                mock_info(router, &[]),
+                ExecuteMsg::Callback(callback.into()),
+            )
+            .unwrap();
+        }
+
+        // the deposited tokens are gone from sent_to_bond, replaced by the shares
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_to_bond.get(DENOM_1), Uint128::zero());
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(5_000));
+        assert_eq!(bond_status.bonded.get(DENOM_2), Uint128::new(10_000));
+
+        let res: GetExchangeRatesResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::GetExchangeRates {}).unwrap())
+                .unwrap();
+        let exchange_rate = res
+            .exchange_rates
+            .iter()
+            .find(|exchange_rate| exchange_rate.denom == DENOM_1)
+            .unwrap();
+        assert_eq!(exchange_rate.tokens, Uint128::new(10_000));
+        assert_eq!(exchange_rate.shares, Uint128::new(5_000));
+        assert_eq!(exchange_rate.rate, Decimal::from_ratio(2u128, 1u128));
+
+        let msg = QueryMsg::GetPositionValue {
+            id: "test_id".into(),
+        };
+        let res: GetPositionValueResponse =
+            from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.values[0].denom, DENOM_1);
+        assert_eq!(res.values[0].shares, Uint128::new(5_000));
+        assert_eq!(res.values[0].value, Uint128::new(11_000));
+        assert_eq!(res.values[1].value, Uint128::new(10_000));
+
+        // unbonded shares come back as tokens
+        let share_amounts = Some(vec![coin(1_000, DENOM_1), coin(0, DENOM_2)]);
+        let msg = ExecuteMsg::StartUnbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts: share_amounts.clone(),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+        let callback = Callback::StartUnbondResponse(StartUnbondResponse {
+            unbond_id: "test_id".into(),
+            nonce: None,
+        });
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap();
+        env.block.time = env.block.time.plus_seconds(LOCK_PERIOD_DENOM_1 + 1);
+        let msg = ExecuteMsg::Unbond {
+            id: "test_id".into(),
+            share_amount: None,
+            share_amounts,
+            recipient: None,
+            msg: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(USER, &[]), msg).unwrap();
+
+        let unbond_response = |shares: u128| {
+            ExecuteMsg::Callback(
+                Callback::UnbondResponse(UnbondResponse {
+                    unbond_id: "test_id".into(),
+                    share_amount: Some(Uint128::new(shares)),
+                    nonce: None,
+                })
+                .into(),
+            )
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(2_200, DENOM_1)]),
+            unbond_response(1_500),
+        )
+        .unwrap_err();
+        assert_eq!(
+            res,
+            ContractError::UnbondFundsTooHigh {
+                amount: coin(1_500, DENOM_1),
+                expected: coin(1_000, DENOM_1),
+            }
+        );
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[coin(2_200, DENOM_1)]),
+            unbond_response(1_000),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: USER.into(),
+                amount: vec![coin(2_200, DENOM_1)]
+            })
+        );
+        let bond_status = get_bond_status(&deps.as_ref(), &env);
+        assert_eq!(bond_status.sent_for_unbond.get(DENOM_1), Uint128::zero());
+        assert_eq!(bond_status.bonded.get(DENOM_1), Uint128::new(4_000));
+
+        // a rate out of the decimal range is rejected instead of panicking
+        let msg = ExecuteMsg::Bond {
+            id: Some("other_id".into()),
+        };
+        let amount = 10u128.pow(21);
+        let msg_info = mock_info(USER, &[coin(amount, DENOM_1), coin(amount, DENOM_2)]);
+        execute(deps.as_mut(), env.clone(), msg_info, msg).unwrap();
+        let callback = Callback::BondResponse(BondResponse {
+            share_amount: Uint128::one(),
+            bond_id: "other_id".into(),
+            nonce: None,
+        });
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(ROUTER_DENOM_1, &[]),
+            ExecuteMsg::Callback(callback.into()),
+        )
+        .unwrap_err();
+        assert!(matches!(res, ContractError::CheckedFromRatio(_)));
+
+        // shares of routers without the conversion query are valued at their last bond rate
+        deps.querier.update_wasm(|_| {
+            SystemResult::Ok(ContractResult::Err(
+                "unknown variant `convert_to_assets`".into(),
+            ))
+        });
+        let msg = QueryMsg::GetPositionValue {
+            id: "test_id".into(),
+        };
+        let res: GetPositionValueResponse =
+            from_binary(&query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.values[0].shares, Uint128::new(4_000));
+        assert_eq!(res.values[0].value, Uint128::new(8_000));
+        assert_eq!(res.values[1].value, Uint128::new(10_000));
     }
 }